@@ -0,0 +1,11 @@
+//! Generates the network messages in `networking_protobuf` from `proto/chess.proto`.
+
+fn main() -> std::io::Result<()> {
+    // protoc comes with the build dependencies, so nothing has to be installed or downloaded
+    let protoc = protoc_bin_vendored::protoc_bin_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e.to_string()))?;
+    std::env::set_var("PROTOC", protoc);
+
+    println!("cargo:rerun-if-changed=proto/chess.proto");
+    prost_build::compile_protos(&["proto/chess.proto"], &["proto/"])
+}
@@ -0,0 +1,108 @@
+//! Engine analysis of the position on the board, running in the background.
+
+use std::io;
+
+use crate::chess_rules::{ChessMove, Position, Side};
+use crate::uci_engine::{Score, UciEngine};
+
+/// A principal variation, scored from White's point of view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysisLine {
+    pub score: Score,
+    pub moves: Vec<ChessMove>,
+    /// `moves` in SAN.
+    pub san: Vec<String>,
+}
+
+/// Keeps an engine searching the current position and collects its best variations.
+pub struct Analysis {
+    engine: UciEngine,
+    /// Position being analysed, used to check the variations and write them in SAN.
+    position: Position,
+    /// Depth of the best variation.
+    pub depth: u32,
+    /// Best variations first, at most as many as asked for when starting.
+    pub lines: Vec<AnalysisLine>,
+}
+
+impl Analysis {
+    /// Starts the engine with `command`, asking it for the best `line_amount` variations.
+    pub fn start(command: &str, line_amount: usize) -> io::Result<Analysis> {
+        let mut engine = UciEngine::start(command)?;
+        engine.set_option("MultiPV", &line_amount.to_string())?;
+        Ok(Analysis {
+            engine,
+            position: Position::initial(),
+            depth: 0,
+            lines: Vec::new(),
+        })
+    }
+
+    /// Starts analysing `position`, reached by playing `moves` from `start`.
+    pub fn analyse(&mut self, position: &Position, start: &Position, moves: &[ChessMove]) -> io::Result<()> {
+        self.position = position.clone();
+        self.depth = 0;
+        self.lines.clear();
+        if position.result().is_some() {
+            // Nothing to analyse once the game is over
+            return self.engine.stop();
+        }
+        self.engine.go_infinite(start, moves)
+    }
+
+    /// Returns whether the engine has stopped answering, e.g. because it crashed.
+    pub fn has_failed(&self) -> bool {
+        self.engine.has_failed()
+    }
+
+    /// Picks up what the engine has found since the last update, without waiting.
+    pub fn update(&mut self) {
+        for info in self.engine.poll_info() {
+            let index = match info.multipv.checked_sub(1) {
+                Some(index) => index,
+                None => continue,
+            };
+            if index > self.lines.len() {
+                // Variations are filled in from the best one
+                continue;
+            }
+
+            // Engines can report moves that are illegal after a stop, so the variation ends at the
+            // first one
+            let mut position = self.position.clone();
+            let mut moves = Vec::new();
+            let mut san = Vec::new();
+            for chess_move in info.pv {
+                if !position.is_legal(chess_move) {
+                    break;
+                }
+                let chess_move = position.normalize(chess_move);
+                san.push(position.san(chess_move));
+                position.make_move(chess_move);
+                moves.push(chess_move);
+            }
+            if moves.is_empty() {
+                continue;
+            }
+
+            let score = match self.position.side_to_move {
+                Side::White => info.score,
+                Side::Black => info.score.flipped(),
+            };
+            let line = AnalysisLine { score, moves, san };
+            if index == self.lines.len() {
+                self.lines.push(line);
+            } else {
+                self.lines[index] = line;
+            }
+            if index == 0 {
+                self.depth = info.depth;
+            }
+        }
+    }
+
+    /// Returns the score of the best variation, once the engine has found one.
+    pub fn score(&self) -> Option<Score> {
+        self.lines.first().map(|line| line.score)
+    }
+}
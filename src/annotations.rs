@@ -0,0 +1,106 @@
+//! Square marks and arrows drawn on the board with the right mouse button.
+
+use piston::GenericEvent;
+use piston::input::{Button, Key};
+
+/// Modifier keys being held down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    /// Tracks presses and releases of the modifier keys.
+    pub fn event<E: GenericEvent>(&mut self, e: &E) {
+        let (key, held) = match (e.press_args(), e.release_args()) {
+            (Some(Button::Keyboard(key)), _) => (key, true),
+            (_, Some(Button::Keyboard(key))) => (key, false),
+            _ => return,
+        };
+        match key {
+            Key::LShift | Key::RShift => self.shift = held,
+            Key::LCtrl | Key::RCtrl => self.ctrl = held,
+            Key::LAlt | Key::RAlt => self.alt = held,
+            _ => {}
+        }
+    }
+}
+
+/// Color of a mark or arrow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnotationColor {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl AnnotationColor {
+    /// Returns the color picked by the held modifier keys: Shift for red, Ctrl for blue, Alt for
+    /// yellow and green without any.
+    pub fn from_modifiers(modifiers: Modifiers) -> AnnotationColor {
+        if modifiers.shift {
+            AnnotationColor::Red
+        } else if modifiers.ctrl {
+            AnnotationColor::Blue
+        } else if modifiers.alt {
+            AnnotationColor::Yellow
+        } else {
+            AnnotationColor::Green
+        }
+    }
+}
+
+/// An arrow between two squares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arrow {
+    pub from: u8,
+    pub to: u8,
+    pub color: AnnotationColor,
+}
+
+/// Marked squares and arrows on the board, squares are board indices like in `ChessBoard::drag`.
+#[derive(Clone, Debug, Default)]
+pub struct Annotations {
+    pub squares: Vec<(u8, AnnotationColor)>,
+    pub arrows: Vec<Arrow>,
+}
+
+impl Annotations {
+    /// Marks a square, or removes its mark if it already has one of the same color.
+    pub fn toggle_square(&mut self, square: u8, color: AnnotationColor) {
+        match self.squares.iter().position(|(marked, _)| *marked == square) {
+            Some(index) => {
+                let (_, old_color) = self.squares.remove(index);
+                if old_color != color {
+                    self.squares.push((square, color));
+                }
+            }
+            None => self.squares.push((square, color)),
+        }
+    }
+
+    /// Adds an arrow, or removes it if the same arrow already exists in the same color.
+    pub fn toggle_arrow(&mut self, from: u8, to: u8, color: AnnotationColor) {
+        let existing = self.arrows
+            .iter()
+            .position(|arrow| arrow.from == from && arrow.to == to);
+        match existing {
+            Some(index) => {
+                let old = self.arrows.remove(index);
+                if old.color != color {
+                    self.arrows.push(Arrow { from, to, color });
+                }
+            }
+            None => self.arrows.push(Arrow { from, to, color }),
+        }
+    }
+
+    /// Removes every mark and arrow.
+    pub fn clear(&mut self) {
+        self.squares.clear();
+        self.arrows.clear();
+    }
+}
@@ -0,0 +1,109 @@
+//! Moves described in words, for screen readers and text-to-speech tools following a game.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use crate::chess_rules::{file_of, piece_kind, square_name, ChessMove, GameResult, PieceKind, Position, Side};
+
+/// Writes a line describing each move to stdout or a file.
+pub struct Announcer {
+    output: Box<dyn Write>,
+}
+
+impl Announcer {
+    /// Announces moves on stdout.
+    pub fn stdout() -> Announcer {
+        Announcer {
+            output: Box::new(io::stdout()),
+        }
+    }
+
+    /// Announces moves at the end of a file, which is created if it doesn't exist.
+    pub fn to_file(path: &str) -> io::Result<Announcer> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Announcer {
+            output: Box::new(file),
+        })
+    }
+
+    /// Returns the announcer asked for by `CHESS_ANNOUNCE`, which is `-` for stdout or a file to
+    /// write to.
+    pub fn from_env() -> Option<Announcer> {
+        let target = std::env::var("CHESS_ANNOUNCE").ok()?;
+        if target == "-" {
+            return Some(Announcer::stdout());
+        }
+        match Announcer::to_file(&target) {
+            Ok(announcer) => Some(announcer),
+            Err(e) => {
+                eprintln!("Could not announce moves to {}: {}", target, e);
+                None
+            }
+        }
+    }
+
+    /// Writes one announcement, flushed right away so listeners hear it as the move is played.
+    pub fn announce(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", text)?;
+        self.output.flush()
+    }
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::White => "White",
+        Side::Black => "Black",
+    }
+}
+
+fn kind_name(kind: PieceKind) -> &'static str {
+    match kind {
+        PieceKind::Pawn => "pawn",
+        PieceKind::Knight => "knight",
+        PieceKind::Bishop => "bishop",
+        PieceKind::Rook => "rook",
+        PieceKind::Queen => "queen",
+        PieceKind::King => "king",
+    }
+}
+
+/// Describes a legal move in `position`, e.g. "Black knight from g8 to f6, check".
+pub fn describe_move(position: &Position, chess_move: ChessMove) -> String {
+    let chess_move = position.normalize(chess_move);
+    let (from, to) = (chess_move.from, chess_move.to);
+    let side = position.side_to_move;
+    let kind = match piece_kind(position.board[from as usize]) {
+        Some(kind) => kind,
+        None => return chess_move.uci(),
+    };
+
+    let mut text = if kind == PieceKind::King && file_of(from).abs_diff(file_of(to)) == 2 {
+        let wing = if file_of(to) == 6 { "kingside" } else { "queenside" };
+        format!("{} castles {}", side_name(side), wing)
+    } else {
+        format!("{} {} from {} to {}", side_name(side), kind_name(kind), square_name(from), square_name(to))
+    };
+
+    match piece_kind(position.board[to as usize]) {
+        Some(captured) => text.push_str(&format!(", takes {}", kind_name(captured))),
+        // A pawn moving diagonally to an empty square takes en passant
+        None if kind == PieceKind::Pawn && file_of(from) != file_of(to) => text.push_str(", takes pawn en passant"),
+        None => {}
+    }
+    if let Some(promotion) = chess_move.promotion {
+        text.push_str(&format!(", promotes to {}", kind_name(promotion)));
+    }
+
+    let mut after = position.clone();
+    after.make_move(chess_move);
+    match after.result() {
+        Some(GameResult::Checkmate { .. }) => text.push_str(", checkmate"),
+        Some(GameResult::Stalemate) => text.push_str(", stalemate"),
+        Some(GameResult::InsufficientMaterial) => text.push_str(", draw by insufficient material"),
+        Some(GameResult::FiftyMoveRule) => text.push_str(", draw by the fifty-move rule"),
+        None if after.in_check() => text.push_str(", check"),
+        None => {}
+    }
+
+    text
+}
@@ -0,0 +1,281 @@
+//! Board editor for setting up a position, to export as FEN or to start a game from.
+
+use piston::GenericEvent;
+use piston::input::{Button, Key, MouseButton};
+use dynchess_lib::ChessPiece;
+
+use crate::chess_graphics::ChessGraphicsSettings;
+use crate::chess_rules::{make_piece, square_name, PieceKind, Position, Side};
+use crate::start_menu::GameMode;
+
+/// Pieces that can be placed, White's in the first row of the palette and Black's in the second.
+pub const PALETTE: [ChessPiece; 12] = [
+    ChessPiece::WKing, ChessPiece::WQueen, ChessPiece::WRook,
+    ChessPiece::WBishop, ChessPiece::WKnight, ChessPiece::WPawn,
+    ChessPiece::BKing, ChessPiece::BQueen, ChessPiece::BRook,
+    ChessPiece::BBishop, ChessPiece::BKnight, ChessPiece::BPawn,
+];
+
+/// Buttons below the palette, in the order they are shown.
+pub const EDITOR_BUTTONS: [EditorButton; 12] = [
+    EditorButton::SideToMove,
+    EditorButton::WhiteKingSide,
+    EditorButton::WhiteQueenSide,
+    EditorButton::BlackKingSide,
+    EditorButton::BlackQueenSide,
+    EditorButton::EnPassant,
+    EditorButton::Clear,
+    EditorButton::Reset,
+    EditorButton::ExportFen,
+    EditorButton::PlayLocal,
+    EditorButton::PlayEngine,
+    EditorButton::Host,
+];
+
+/// Square the keyboard cursor starts on, e2.
+const KEYBOARD_CURSOR_START: u8 = 12;
+
+/// A button of the board editor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorButton {
+    /// Switches the side to move.
+    SideToMove,
+    WhiteKingSide,
+    WhiteQueenSide,
+    BlackKingSide,
+    BlackQueenSide,
+    /// Cycles through the squares that can be the en passant square.
+    EnPassant,
+    /// Removes every piece.
+    Clear,
+    /// Sets up the initial position.
+    Reset,
+    /// Prints the position as FEN.
+    ExportFen,
+    PlayLocal,
+    PlayEngine,
+    Host,
+}
+
+/// What the player did in the board editor.
+pub enum EditorOutcome {
+    /// Start a game of this mode from the position.
+    Start(GameMode, Position),
+    /// Go back to the start menu.
+    Cancelled,
+}
+
+/// Handles events for the board editor.
+pub struct BoardEditor {
+    /// The position being set up.
+    pub position: Position,
+    /// Piece placed by clicking the board, `ChessPiece::Empty` when clicks remove pieces.
+    pub selected_piece: ChessPiece,
+    /// Board index of the hovered square, moved by the mouse and the arrow keys.
+    pub hovered_square: Option<u8>,
+    /// Shown in the status bar until the next change, e.g. the exported FEN.
+    pub message: Option<String>,
+    /// Address to host a game on.
+    host_address: String,
+    /// Command that starts the engine to play against.
+    engine_command: String,
+    /// Stores the mouse coords.
+    mouse_coords: [f64; 2],
+}
+
+impl BoardEditor {
+    /// Creates a board editor set up with the initial position.
+    pub fn new(host_address: String, engine_command: String) -> BoardEditor {
+        BoardEditor {
+            position: Position::initial(),
+            selected_piece: ChessPiece::WQueen,
+            hovered_square: None,
+            message: None,
+            host_address,
+            engine_command,
+            mouse_coords: [0.0; 2],
+        }
+    }
+
+    /// Returns the text shown on a button.
+    pub fn button_label(&self, button: EditorButton) -> String {
+        let castling = self.position.castling;
+        let check_box = |checked: bool, label: &str| format!("[{}] {}", if checked { "x" } else { " " }, label);
+
+        match button {
+            EditorButton::SideToMove => match self.position.side_to_move {
+                Side::White => String::from("White to move"),
+                Side::Black => String::from("Black to move"),
+            },
+            EditorButton::WhiteKingSide => check_box(castling.white_king_side, "White may castle O-O"),
+            EditorButton::WhiteQueenSide => check_box(castling.white_queen_side, "White may castle O-O-O"),
+            EditorButton::BlackKingSide => check_box(castling.black_king_side, "Black may castle O-O"),
+            EditorButton::BlackQueenSide => check_box(castling.black_queen_side, "Black may castle O-O-O"),
+            EditorButton::EnPassant => match self.position.en_passant {
+                Some(square) => format!("En passant: {}", square_name(square)),
+                None => String::from("En passant: -"),
+            },
+            EditorButton::Clear => String::from("Clear the board"),
+            EditorButton::Reset => String::from("Starting position"),
+            EditorButton::ExportFen => String::from("Export FEN"),
+            EditorButton::PlayLocal => String::from("Play locally"),
+            EditorButton::PlayEngine => String::from("Play the engine"),
+            EditorButton::Host => String::from("Host a game"),
+        }
+    }
+
+    /// Returns the message, or what is wrong with the position, for the status bar.
+    pub fn status_text(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        match self.position.validate() {
+            Ok(()) => String::from("Position is valid"),
+            Err(e) => e,
+        }
+    }
+
+    /// Handles events, returns the outcome once the player starts a game or leaves.
+    ///
+    /// Clicking the board places the piece selected in the palette, or removes it if it is
+    /// already there, and right-clicking removes any piece. With the keyboard, the arrow keys move
+    /// the hovered square, typing a piece letter places it there, uppercase for White, and Delete
+    /// or Backspace removes it. Escape goes back to the start menu.
+    pub fn event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) -> Option<EditorOutcome> {
+        if let Some(pos) = e.mouse_cursor_args() {
+            self.mouse_coords = pos;
+            // Only moving the mouse moves the hover, so it doesn't undo the arrow keys
+            self.hovered_square = settings.square_at(pos);
+        }
+        let square = settings.square_at(self.mouse_coords);
+
+        if let Some(text) = e.text_args() {
+            for letter in text.chars() {
+                let kind = PieceKind::from_letter(letter.to_ascii_uppercase());
+                let side = if letter.is_ascii_uppercase() { Side::White } else { Side::Black };
+                if let Some(kind) = kind {
+                    let square = self.hovered_square.unwrap_or(KEYBOARD_CURSOR_START);
+                    self.set_piece(square, make_piece(side, kind));
+                }
+            }
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Escape)) => return Some(EditorOutcome::Cancelled),
+            Some(Button::Keyboard(Key::Delete)) | Some(Button::Keyboard(Key::Backspace)) => {
+                if let Some(square) = self.hovered_square {
+                    self.set_piece(square, ChessPiece::Empty);
+                }
+            }
+            Some(Button::Keyboard(key)) => {
+                let square = self.hovered_square.unwrap_or(KEYBOARD_CURSOR_START);
+                let (file, rank) = (square % 8, square / 8);
+                self.hovered_square = match key {
+                    Key::Left => Some(file.saturating_sub(1) + rank * 8),
+                    Key::Right => Some((file + 1).min(7) + rank * 8),
+                    Key::Up => Some(file + (rank + 1).min(7) * 8),
+                    Key::Down => Some(file + rank.saturating_sub(1) * 8),
+                    _ => self.hovered_square,
+                };
+            }
+            Some(Button::Mouse(MouseButton::Left)) => {
+                if let Some(square) = square {
+                    let piece = if self.position.board[square as usize] == self.selected_piece {
+                        ChessPiece::Empty
+                    } else {
+                        self.selected_piece
+                    };
+                    self.set_piece(square, piece);
+                }
+
+                let palette = (0..PALETTE.len()).map(|index| settings.palette_rect(index));
+                if let Some(index) = settings.rect_at(self.mouse_coords, palette) {
+                    // Clicking the selected piece again switches to removing pieces
+                    self.selected_piece = if self.selected_piece == PALETTE[index] {
+                        ChessPiece::Empty
+                    } else {
+                        PALETTE[index]
+                    };
+                }
+
+                let buttons = (0..EDITOR_BUTTONS.len()).map(|index| settings.editor_button_rect(index));
+                if let Some(index) = settings.rect_at(self.mouse_coords, buttons) {
+                    return self.press(EDITOR_BUTTONS[index]);
+                }
+            }
+            Some(Button::Mouse(MouseButton::Right)) => {
+                if let Some(square) = square {
+                    self.set_piece(square, ChessPiece::Empty);
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Puts `piece` on `square`, or empties it.
+    fn set_piece(&mut self, square: u8, piece: ChessPiece) {
+        self.position.board[square as usize] = piece;
+        self.changed();
+    }
+
+    /// Clears the message and the en passant square if no pawn can have passed over it anymore.
+    fn changed(&mut self) {
+        let candidates = self.position.en_passant_candidates();
+        self.position.en_passant = self.position.en_passant.filter(|square| candidates.contains(square));
+        self.message = None;
+    }
+
+    /// Does what a button does, returns the outcome if it starts a game.
+    fn press(&mut self, button: EditorButton) -> Option<EditorOutcome> {
+        let castling = &mut self.position.castling;
+        match button {
+            EditorButton::SideToMove => self.position.side_to_move = self.position.side_to_move.opponent(),
+            EditorButton::WhiteKingSide => castling.white_king_side = !castling.white_king_side,
+            EditorButton::WhiteQueenSide => castling.white_queen_side = !castling.white_queen_side,
+            EditorButton::BlackKingSide => castling.black_king_side = !castling.black_king_side,
+            EditorButton::BlackQueenSide => castling.black_queen_side = !castling.black_queen_side,
+            EditorButton::EnPassant => {
+                // Goes through the candidates in order and then back to none
+                let candidates = self.position.en_passant_candidates();
+                self.position.en_passant = match self.position.en_passant {
+                    None => candidates.first().copied(),
+                    Some(square) => candidates.into_iter().find(|candidate| *candidate > square),
+                };
+            }
+            EditorButton::Clear => self.position = Position::empty(),
+            EditorButton::Reset => self.position = Position::initial(),
+            EditorButton::ExportFen => {
+                if let Err(e) = self.position.validate() {
+                    self.message = Some(format!("Can't export: {}", e));
+                    return None;
+                }
+                self.message = Some(format!("FEN: {}", self.position.to_fen()));
+                return None;
+            }
+            EditorButton::PlayLocal => return self.start(GameMode::Local),
+            EditorButton::PlayEngine => {
+                let command = self.engine_command.clone();
+                return self.start(GameMode::Engine { command });
+            }
+            EditorButton::Host => {
+                let address = self.host_address.clone();
+                return self.start(GameMode::Host { address });
+            }
+        }
+        self.changed();
+        None
+    }
+
+    /// Returns the outcome starting a game from the position, or sets a message if it isn't valid.
+    fn start(&mut self, mode: GameMode) -> Option<EditorOutcome> {
+        match self.position.validate() {
+            Ok(()) => Some(EditorOutcome::Start(mode, self.position.clone())),
+            Err(e) => {
+                self.message = Some(format!("Can't start: {}", e));
+                None
+            }
+        }
+    }
+}
@@ -1,121 +1,543 @@
 //! Chess controller.
 
-use std::borrow::Borrow;
-use std::num::IntErrorKind::Empty;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 use piston::GenericEvent;
-use piston::input::{Button, MouseButton};
-use dynchess_lib::{ChessBoard, ChessPiece};
-use crate::networking::{ConnectionType, Networking, State};
-use crate::networking_protobuf::{S2cConnectAck, S2cMessage, s2c_message, c2s_message};
+use piston::input::{Button, Key, MouseButton};
+use dynchess_lib::ChessPiece;
+use crate::annotations::{AnnotationColor, Annotations, Modifiers};
+use crate::chess_graphics::ChessGraphicsSettings;
+use crate::clipboard::{Clipboard, SystemClipboard};
+use crate::diagram::{self, Diagram};
+use crate::chess_rules::{piece_side, ChessMove, Position};
+use crate::game::{Game, Opponent};
+use crate::networking::MAX_CHAT_LENGTH;
+use crate::game_tree::GameTree;
+
+/// Square the keyboard cursor starts on, e2 from White's side.
+const KEYBOARD_CURSOR_START: [u8; 2] = [4, 6];
 
 /// Handles events for Chess.
 pub struct ChessController {
-    /// Stores the chess board.
-    pub chess_engine: ChessBoard,
+    /// The game being played.
+    pub game: Game,
+    /// Move list rows scrolled up from the latest move.
+    pub move_list_scroll: usize,
+    /// Moves in the history when the move list last followed the viewed move.
+    followed_moves: usize,
+    /// Every line played in a local game, to undo, redo and jump between moves.
+    pub tree: GameTree,
     /// Stores the selected square.
     pub selected_square: Option<[u8; 2]>,
     /// Stores the hovered square.
     pub hovered_square: Option<[u8; 2]>,
     /// Stores the mouse coords.
     mouse_coords: [f64; 2],
-    /// Networking
-    pub networking: Networking,
+    /// Squares marked and arrows drawn with the right mouse button.
+    pub annotations: Annotations,
+    /// Square where the right mouse button was pressed.
+    annotation_start: Option<u8>,
+    /// Modifier keys being held down.
+    modifiers: Modifiers,
+    /// Move being typed in SAN or UCI notation, played with Enter.
+    pub move_input: String,
+    /// Clipboard positions are copied to and pasted from as FEN.
+    pub clipboard: Box<dyn Clipboard>,
+    /// Chat message being typed, while typing goes to the chat instead of the move input.
+    pub chat_input: Option<String>,
+    /// Chat messages scrolled back from the latest one.
+    pub chat_scroll: usize,
 }
 
 impl ChessController {
     /// Creates a new chess board controller.
-    pub fn new() -> ChessController {
+    pub fn new(opponent: Opponent) -> ChessController {
+        ChessController::from_position(opponent, Position::initial())
+    }
+
+    /// Creates a chess board controller for a game starting from `start`.
+    pub fn from_position(opponent: Opponent, start: Position) -> ChessController {
         let chess_controller = ChessController {
-            chess_engine: ChessBoard::init_position(),
+            game: Game::from_position(opponent, start),
+            move_list_scroll: 0,
+            followed_moves: 0,
+            tree: GameTree::new(),
             selected_square: None,
             hovered_square: None,
             mouse_coords: [0.0; 2],
-            networking: Networking::new(),
+            annotations: Annotations::default(),
+            annotation_start: None,
+            modifiers: Modifiers::default(),
+            move_input: String::new(),
+            clipboard: Box::new(SystemClipboard),
+            chat_input: None,
+            chat_scroll: 0,
         };
         chess_controller
     }
 
     /// Handles events.
-    pub fn event<E: GenericEvent>(&mut self, offset: [f64; 2], size: f64, square_amount: f64, e: &E) {
-        // Initial connection
-        // println!("looping event");
-        match self.networking.connection.clone() {
-            ConnectionType::Host(host) => {
-                if host.msg.is_some(){
-                    match host.msg.unwrap() {
-                        // s2c_message::Msg::Move(_) => {}
-                        s2c_message::Msg::ConnectAck(_) => {
-                            self.networking.receive_packet();
-                            // self.networking.send_packet(0, 0);
-                            return
-                        }
-                        // s2c_message::MsgMsg::MoveAck(_) => {}
-                        _ => {}
-                    }
-                }
+    pub fn event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) {
+        self.game.update();
+
+        if let Some(pos) = e.mouse_cursor_args() {
+            self.mouse_coords = pos;
+        }
+
+        self.modifiers.event(e);
+
+        // The takeback prompt covers the board until it is answered
+        if self.game.takeback_request.is_some() {
+            self.takeback_prompt_event(settings, e);
+            return;
+        }
+
+        // Typing a chat message takes the keyboard, the mouse still plays
+        if self.chat_event(settings, e) {
+            return;
+        }
+
+        self.annotation_event(settings, e);
+        self.move_input_event(e);
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::F2)) => self.save_diagram(settings, "svg"),
+            Some(Button::Keyboard(Key::F3)) => self.save_diagram(settings, "png"),
+            Some(Button::Keyboard(Key::F4)) => self.game.toggle_analysis(),
+            Some(Button::Keyboard(Key::F5)) => self.game.save_pgn(),
+            Some(Button::Keyboard(Key::C)) if self.modifiers.ctrl => self.copy_fen(),
+            Some(Button::Keyboard(Key::V)) if self.modifiers.ctrl => self.paste_fen(),
+            // Local games keep the moves to redo them, other games ask the opponent
+            Some(Button::Keyboard(Key::Z)) if self.modifiers.ctrl && self.can_navigate() => {
+                self.go_to_ply(self.game.history.len().saturating_sub(1));
+            }
+            Some(Button::Keyboard(Key::Z)) if self.modifiers.ctrl => {
+                self.game.takeback();
+                self.selected_square = None;
             }
-            ConnectionType::Client(client) => {
-                if client.msg.is_some() {
-                    match client.msg.unwrap() {
-                        // c2s_message::Msg::Move(_) => {}
-                        c2s_message::Msg::ConnectRequest(_) => {
-                            self.networking.receive_packet();
-                            self.networking.send_packet(None);
-                            return
-                        }
-                        _ => {}
+            Some(Button::Keyboard(Key::Y)) if self.modifiers.ctrl && self.can_navigate() => {
+                self.go_to_ply(self.game.history.len() + 1);
+            }
+            Some(Button::Keyboard(Key::Up)) if self.modifiers.ctrl && self.can_navigate() => self.switch_variation(false),
+            Some(Button::Keyboard(Key::Down)) if self.modifiers.ctrl && self.can_navigate() => self.switch_variation(true),
+            Some(Button::Mouse(MouseButton::Left)) if self.can_navigate() => {
+                let first_row = self.move_list_first_row(settings);
+                let cells = (0..self.move_list_rows(settings) * 2)
+                    .map(|cell| settings.move_list_cell_rect(cell / 2, cell % 2));
+                if let Some(cell) = settings.rect_at(self.mouse_coords, cells) {
+                    // The first cell is empty when Black moved first
+                    let index = (first_row * 2 + cell).checked_sub(self.game.first_move_offset());
+                    if let Some(index) = index.filter(|index| *index < self.move_list().len()) {
+                        self.go_to_ply(index + 1);
                     }
                 }
             }
+            _ => {}
+        }
+
+        if let Some([_, scroll_y]) = e.mouse_scroll_args() {
+            let chat = settings.rect_at(self.mouse_coords, std::iter::once(settings.chat_rect())).is_some();
+            if self.game.has_chat() && chat {
+                self.scroll_chat(scroll_y, settings.chat_rows - 1);
+            } else if settings.move_list_contains(self.mouse_coords) {
+                self.scroll_move_list(scroll_y, self.move_list_rows(settings));
+            }
+        }
+
+        // During the opponent's turn, moving pieces queues premoves instead
+        if self.game.is_players_turn() || self.game.can_premove() {
+            self.board_event(settings, e);
+        }
+
+        self.tree.record(&self.game.history);
+
+        // Follow the viewed move, whoever played it, keeping it on the last row shown
+        if self.game.history.len() != self.followed_moves {
+            self.followed_moves = self.game.history.len();
+            let rows = self.move_list_row_count();
+            let max_scroll = rows.saturating_sub(self.move_list_rows(settings));
+            let viewed_row = (self.followed_moves + self.game.first_move_offset() + 1) / 2;
+            self.move_list_scroll = rows.saturating_sub(viewed_row).min(max_scroll);
+        }
+    }
+
+    /// Returns whether moves can be undone, redone and jumped between, which is in local games, as
+    /// an opponent's moves can't be undone.
+    pub fn can_navigate(&self) -> bool {
+        matches!(self.game.opponent, Opponent::Local)
+    }
+
+    /// Returns the moves in the move list, the moves played up to the viewed position followed by
+    /// the moves redo would play.
+    pub fn move_list(&self) -> Vec<&str> {
+        let mut moves: Vec<&str> = self.game.history.iter().map(|played| played.san.as_str()).collect();
+        if self.can_navigate() {
+            moves.extend(self.tree.continuation(&self.game.history).iter().map(|node| node.san.as_str()));
+        }
+        moves
+    }
+
+    /// Returns how many rows the whole move list takes, one per full move.
+    pub fn move_list_row_count(&self) -> usize {
+        match self.move_list().len() {
+            0 => 0,
+            moves => (self.game.first_move_offset() + moves + 1) / 2,
+        }
+    }
+
+    /// Returns the first row of the move list shown, scrolling up from the last row.
+    pub fn move_list_first_row(&self, settings: &ChessGraphicsSettings) -> usize {
+        let rows = self.move_list_row_count();
+        let visible_rows = self.move_list_rows(settings);
+        rows.saturating_sub(visible_rows + self.move_list_scroll)
+    }
+
+    /// Returns how many rows of the move list are shown, fewer while analysing or chatting.
+    pub fn move_list_rows(&self, settings: &ChessGraphicsSettings) -> usize {
+        settings.move_list_rows(self.game.analysis.is_some(), self.game.has_chat())
+    }
+
+    /// Shows the position after the first `ply` moves of the move list, taking back or redoing
+    /// moves to get there.
+    fn go_to_ply(&mut self, ply: usize) {
+        let played = self.game.history.len();
+        if ply <= played {
+            self.game.take_back_to(ply);
+        } else {
+            let redo: Vec<ChessMove> = self.tree
+                .continuation(&self.game.history)
+                .iter()
+                .take(ply - played)
+                .map(|node| node.chess_move)
+                .collect();
+            // Jumping ahead would announce every move on the way
+            let announcer = if redo.len() > 1 { self.game.announcer.take() } else { None };
+            for chess_move in redo {
+                self.game.apply_move(chess_move);
+            }
+            if announcer.is_some() {
+                self.game.announcer = announcer;
+            }
+        }
+        self.selected_square = None;
+    }
+
+    /// Replaces the last move with the next or the previous move played from the same position.
+    fn switch_variation(&mut self, next: bool) {
+        let alternatives: Vec<ChessMove> = self.tree
+            .alternatives(&self.game.history)
+            .iter()
+            .map(|node| node.chess_move)
+            .collect();
+        let last = match self.game.history.last() {
+            Some(played) if alternatives.len() > 1 => played.chess_move,
+            _ => {
+                self.game.notice = Some(String::from("No other moves were played here"));
+                return;
+            }
         };
 
-        // When connected
-        // println!("connected, state: {:?}", self.networking.state);
-        match self.networking.state {
-            State::Playing => {
-                if let Some(pos) = e.mouse_cursor_args() {
-                    self.mouse_coords = pos;
-                    // println!("{:?}", self.mouse_pos)
-                }
+        let index = alternatives.iter().position(|chess_move| *chess_move == last).unwrap_or(0);
+        let index = if next {
+            (index + 1) % alternatives.len()
+        } else {
+            (index + alternatives.len() - 1) % alternatives.len()
+        };
+        self.game.take_back_to(self.game.history.len() - 1);
+        self.game.apply_move(alternatives[index]);
+        self.game.notice = Some(format!("Variation {} of {}", index + 1, alternatives.len()));
+        self.selected_square = None;
+    }
 
-                let x = self.mouse_coords[0] - offset[0];
-                let y = self.mouse_coords[1] - offset[1];
-                // Check that coordinates are inside board boundaries.
-                if x >= 0.0 && x < size && y >= 0.0 && y < size {
-                    // Compute the cell position.
-                    let (coords_x, coords_y) = ((x / size * square_amount) as u8,
-                                                (y / size * square_amount) as u8);
-                    self.hovered_square = Some([coords_x, coords_y]);
-                    if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
-                        let to_coords_u8 = coords_x + (7 - coords_y) * 8;
-                        if self.selected_square.is_some(){
-                            let selected_coords = self.selected_square.unwrap();
-                            let selected_coords_to_u8 = selected_coords[0] + (7 - selected_coords[1]) * 8;
-                            // println!("{:?}, {:?}", selected_coords_to_u8, to_coords_u8);
-                            self.chess_engine.drag(selected_coords_to_u8, to_coords_u8);
-                            self.selected_square = None;
-
-                            // println!("try sending packet");
-                            self.networking.send_packet(Some([selected_coords_to_u8, to_coords_u8]));
-                            // println!("sent packet");
-                            self.networking.state = State::WaitingForOpponent;
-                            // println!("changing state {:?}", self.networking.state);
-                        }
-                        else {
-                            if !(self.chess_engine.get_piece(to_coords_u8) == ChessPiece::Empty) {
-                                self.selected_square = Some([coords_x, coords_y])
-                            }
-                        }
-                    }
-                }
+    /// Returns a diagram of the current position with the selected square and annotations.
+    pub fn diagram(&self) -> Diagram {
+        Diagram {
+            board: self.game.position.board,
+            highlighted_square: self.selected_square.map(|square| square[0] + (7 - square[1]) * 8),
+            annotations: self.annotations.clone(),
+        }
+    }
+
+    /// Returns the current position as FEN.
+    pub fn fen(&self) -> String {
+        self.game.position.to_fen()
+    }
+
+    /// Starts over from a position given as FEN, returns why not if it can't be loaded.
+    pub fn load_fen(&mut self, fen: &str) -> Result<(), String> {
+        let start = Position::from_fen(fen.trim())?;
+        self.game.set_up(start)?;
+        self.tree = GameTree::new();
+        self.selected_square = None;
+        self.annotations = Annotations::default();
+        self.move_input.clear();
+        self.move_list_scroll = 0;
+        Ok(())
+    }
+
+    /// Copies the current position to the clipboard as FEN.
+    fn copy_fen(&mut self) {
+        let fen = self.fen();
+        self.game.notice = Some(match self.clipboard.set_text(&fen) {
+            Ok(()) => format!("Copied {}", fen),
+            Err(e) => format!("Could not copy: {}", e),
+        });
+    }
+
+    /// Starts over from the position on the clipboard.
+    fn paste_fen(&mut self) {
+        let result = self.clipboard
+            .get_text()
+            .map_err(|e| format!("Could not paste: {}", e))
+            .and_then(|text| self.load_fen(&text).map_err(|e| format!("Could not load FEN: {}", e)));
+        self.game.notice = Some(match result {
+            Ok(()) => String::from("Loaded position from the clipboard"),
+            Err(e) => e,
+        });
+    }
+
+    /// Saves a diagram of the current position in the working directory.
+    fn save_diagram(&mut self, settings: &ChessGraphicsSettings, extension: &str) {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = PathBuf::from(format!("position-{}.{}", seconds, extension));
+
+        self.game.notice = Some(match diagram::save(&self.diagram(), settings, &path) {
+            Ok(()) => format!("Saved diagram to {}", path.display()),
+            Err(e) => format!("Could not save diagram: {}", e),
+        });
+    }
+
+    /// Handles the chat of network games, returns whether the event went to the chat.
+    ///
+    /// Tab or clicking the input row starts typing a message, which Enter sends, and Tab or
+    /// clicking elsewhere goes back to typing moves.
+    fn chat_event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) -> bool {
+        if !self.game.has_chat() {
+            return false;
+        }
+
+        let on_input = settings.rect_at(self.mouse_coords, std::iter::once(settings.chat_input_rect())).is_some();
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Tab)) => {
+                self.chat_input = match self.chat_input {
+                    Some(_) => None,
+                    None => Some(String::new()),
+                };
+                return true;
+            }
+            Some(Button::Mouse(MouseButton::Left)) if on_input => {
+                self.chat_input.get_or_insert_with(String::new);
+                return true;
             }
-            State::WaitingForOpponent => {
-                if let Some(buf) = self.networking.receive_packet() {
-                    // self.networking.state = State::Playing;
+            Some(Button::Mouse(MouseButton::Left)) => self.chat_input = None,
+            _ => {}
+        }
+
+        let text = match &mut self.chat_input {
+            Some(text) => text,
+            None => return false,
+        };
+        if let Some(typed) = e.text_args() {
+            let room = MAX_CHAT_LENGTH.saturating_sub(text.chars().count());
+            text.extend(typed.chars().filter(|c| !c.is_control()).take(room));
+            return true;
+        }
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Backspace)) => {
+                text.pop();
+            }
+            Some(Button::Keyboard(Key::Return)) => {
+                let text = std::mem::take(text);
+                self.game.send_chat(&text);
+                self.chat_input = None;
+                self.chat_scroll = 0;
+            }
+            Some(Button::Keyboard(_)) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Scrolls the chat, where a positive `amount` scrolls towards the first message.
+    fn scroll_chat(&mut self, amount: f64, visible_rows: usize) {
+        let max_scroll = self.game.chat.len().saturating_sub(visible_rows);
+        self.chat_scroll = if amount > 0.0 {
+            (self.chat_scroll + 1).min(max_scroll)
+        } else {
+            self.chat_scroll.saturating_sub(1)
+        };
+    }
 
-                    self.chess_engine.drag(buf[0], buf[1]);
+    /// Answers the opponent's takeback request with the prompt's buttons, or Y and N.
+    fn takeback_prompt_event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) {
+        let accepted = match e.press_args() {
+            Some(Button::Keyboard(Key::Y)) => true,
+            Some(Button::Keyboard(Key::N)) => false,
+            Some(Button::Mouse(MouseButton::Left)) => {
+                let buttons = (0..2).map(|index| settings.takeback_button_rect(index));
+                match settings.rect_at(self.mouse_coords, buttons) {
+                    Some(index) => index == 0,
+                    None => return,
                 }
             }
+            _ => return,
+        };
+        self.game.answer_takeback(accepted);
+        self.selected_square = None;
+    }
+
+    /// Handles marking squares and drawing arrows with the right mouse button.
+    ///
+    /// Right-click a square to mark it, right-drag between squares to draw an arrow, and left-click
+    /// the board to clear everything.
+    fn annotation_event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) {
+        let square = settings.square_at(self.mouse_coords);
+
+        match e.press_args() {
+            // Right-clicking cancels premoves before it marks squares again
+            Some(Button::Mouse(MouseButton::Right)) if !self.game.premoves.is_empty() => {
+                self.game.cancel_premoves();
+                self.selected_square = None;
+            }
+            Some(Button::Mouse(MouseButton::Right)) => self.annotation_start = square,
+            Some(Button::Mouse(MouseButton::Left)) if square.is_some() => self.annotations.clear(),
+            _ => {}
+        }
+
+        if let Some(Button::Mouse(MouseButton::Right)) = e.release_args() {
+            let color = AnnotationColor::from_modifiers(self.modifiers);
+            match (self.annotation_start.take(), square) {
+                (Some(from), Some(to)) if from == to => self.annotations.toggle_square(to, color),
+                (Some(from), Some(to)) => self.annotations.toggle_arrow(from, to, color),
+                _ => {}
+            }
         }
     }
+
+    /// Handles selecting and moving pieces with the mouse or the keyboard, returns the move if one
+    /// was played.
+    ///
+    /// The arrow keys move the hovered square like the mouse does, and Enter or Space click it.
+    /// Enter plays the typed move instead when one has been typed.
+    fn board_event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) -> Option<ChessMove> {
+        let (offset, size, square_amount) = (settings.offset, settings.size, settings.square_amount);
+        let x = self.mouse_coords[0] - offset[0];
+        let y = self.mouse_coords[1] - offset[1];
+        // Check that coordinates are inside board boundaries.
+        if x >= 0.0 && x < size && y >= 0.0 && y < size {
+            // Compute the cell position.
+            let (coords_x, coords_y) = ((x / size * square_amount) as u8,
+                                        (y / size * square_amount) as u8);
+            // Only moving the mouse moves the hover, so it doesn't undo the arrow keys
+            if e.mouse_cursor_args().is_some() {
+                self.hovered_square = Some([coords_x, coords_y]);
+            }
+            if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+                return self.click_square([coords_x, coords_y]);
+            }
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Return)) if !self.move_input.is_empty() => self.play_typed_move(),
+            Some(Button::Keyboard(Key::Return)) | Some(Button::Keyboard(Key::Space)) => {
+                self.hovered_square.and_then(|square| self.click_square(square))
+            }
+            // Ctrl shortcuts don't move the cursor
+            Some(Button::Keyboard(_)) if self.modifiers.ctrl => None,
+            Some(Button::Keyboard(key)) => {
+                let [column, row] = self.hovered_square.unwrap_or(KEYBOARD_CURSOR_START);
+                self.hovered_square = match key {
+                    Key::Left => Some([column.saturating_sub(1), row]),
+                    Key::Right => Some([(column + 1).min(7), row]),
+                    Key::Up => Some([column, row.saturating_sub(1)]),
+                    Key::Down => Some([column, (row + 1).min(7)]),
+                    _ => self.hovered_square,
+                };
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Selects the piece on a square, or moves the selected piece there.
+    ///
+    /// `square` is a column and row from the top left, like `selected_square`.
+    fn click_square(&mut self, square: [u8; 2]) -> Option<ChessMove> {
+        if !self.game.is_players_turn() {
+            self.premove_click(square);
+            return None;
+        }
+
+        let [coords_x, coords_y] = square;
+        let to_coords_u8 = coords_x + (7 - coords_y) * 8;
+        if let Some(selected_coords) = self.selected_square {
+            let selected_coords_to_u8 = selected_coords[0] + (7 - selected_coords[1]) * 8;
+            self.selected_square = None;
+
+            let chess_move = ChessMove::new(selected_coords_to_u8, to_coords_u8);
+            if self.game.play(chess_move) {
+                return Some(chess_move);
+            }
+            else if piece_side(self.game.position.board[to_coords_u8 as usize]) == Some(self.game.position.side_to_move) {
+                // Clicking another own piece selects it instead
+                self.selected_square = Some([coords_x, coords_y])
+            }
+        }
+        else if self.game.position.board[to_coords_u8 as usize] != ChessPiece::Empty {
+            self.selected_square = Some([coords_x, coords_y])
+        }
+        None
+    }
+
+    /// Selects one of the player's pieces, or queues a premove of the selected piece, during the
+    /// opponent's turn.
+    ///
+    /// Pieces are where the premoves already queued leave them.
+    fn premove_click(&mut self, square: [u8; 2]) {
+        let [coords_x, coords_y] = square;
+        let to = coords_x + (7 - coords_y) * 8;
+        let board = self.game.premove_board();
+        let own_piece = self.game.player_side().is_some_and(|side| piece_side(board[to as usize]) == Some(side));
+
+        match self.selected_square.take() {
+            _ if own_piece => self.selected_square = Some(square),
+            Some([from_x, from_y]) => self.game.premove(ChessMove::new(from_x + (7 - from_y) * 8, to)),
+            None => {}
+        }
+    }
+
+    /// Plays the move typed in SAN or UCI notation.
+    fn play_typed_move(&mut self) -> Option<ChessMove> {
+        let text = std::mem::take(&mut self.move_input);
+        let chess_move = self.game.play_typed(&text)?;
+        self.selected_square = None;
+        Some(chess_move)
+    }
+
+    /// Collects the move being typed, letters and digits and the marks used in SAN.
+    fn move_input_event<E: GenericEvent>(&mut self, e: &E) {
+        // Ctrl shortcuts aren't typed
+        if self.modifiers.ctrl {
+            return;
+        }
+        if let Some(text) = e.text_args() {
+            self.move_input.extend(text.chars().filter(|c| c.is_ascii_alphanumeric() || "+#=-".contains(*c)));
+        }
+        if let Some(Button::Keyboard(Key::Backspace)) = e.press_args() {
+            self.move_input.pop();
+        }
+    }
+
+    /// Scrolls the move list, where a positive `amount` scrolls towards the first move.
+    fn scroll_move_list(&mut self, amount: f64, visible_rows: usize) {
+        let rows = self.move_list_row_count();
+        let max_scroll = rows.saturating_sub(visible_rows);
+        self.move_list_scroll = if amount > 0.0 {
+            (self.move_list_scroll + 1).min(max_scroll)
+        } else {
+            self.move_list_scroll.saturating_sub(1)
+        };
+    }
 }
\ No newline at end of file
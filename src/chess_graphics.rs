@@ -1,15 +1,99 @@
 //! Chess board view.
 
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fmt;
+use std::path::PathBuf;
 use graphics::types::Color;
-use graphics::{Context, Graphics, Line, Rectangle, Text, Image};
-use opengl_graphics::{Texture, TextureSettings};
+use graphics::{Context, Ellipse, Graphics, ImageSize, Line, Rectangle, Text, Image, Transformed};
+use graphics::glyph_cache::rusttype::GlyphCache;
+use image::RgbaImage;
+use texture::{CreateTexture, Format, TextureSettings, UpdateTexture};
 
-use dynchess_lib::{ChessPiece, ChessPieceType};
-use graphics::rectangle::square;
+use dynchess_lib::ChessPiece;
 
+use crate::annotations::{AnnotationColor, Annotations};
+use crate::analysis::Analysis;
+use crate::board_editor::{BoardEditor, EDITOR_BUTTONS, PALETTE};
 use crate::chess_controller::ChessController;
+use crate::game::ANALYSIS_LINES;
+use crate::replay::GameChooser;
+use crate::start_menu::{StartMenu, WaitingScreen};
+use crate::chess_rules::{make_piece, piece_kind, piece_side, PieceKind, Side};
+
+/// Piece sprites, embedded so the binary can be launched from any directory.
+pub(crate) const SPRITES: [(ChessPiece, &str, &[u8]); 12] = [
+    (ChessPiece::WPawn, "w_pawn.png", include_bytes!("../sprites/w_pawn.png")),
+    (ChessPiece::WBishop, "w_bishop.png", include_bytes!("../sprites/w_bishop.png")),
+    (ChessPiece::WKnight, "w_knight.png", include_bytes!("../sprites/w_knight.png")),
+    (ChessPiece::WRook, "w_rook.png", include_bytes!("../sprites/w_rook.png")),
+    (ChessPiece::WQueen, "w_queen.png", include_bytes!("../sprites/w_queen.png")),
+    (ChessPiece::WKing, "w_king.png", include_bytes!("../sprites/w_king.png")),
+    (ChessPiece::BPawn, "b_pawn.png", include_bytes!("../sprites/b_pawn.png")),
+    (ChessPiece::BBishop, "b_bishop.png", include_bytes!("../sprites/b_bishop.png")),
+    (ChessPiece::BKnight, "b_knight.png", include_bytes!("../sprites/b_knight.png")),
+    (ChessPiece::BRook, "b_rook.png", include_bytes!("../sprites/b_rook.png")),
+    (ChessPiece::BQueen, "b_queen.png", include_bytes!("../sprites/b_queen.png")),
+    (ChessPiece::BKing, "b_king.png", include_bytes!("../sprites/b_king.png")),
+];
+
+/// Kinds of pieces that can be captured, in the order captured pieces are shown.
+const CAPTURABLE_KINDS: [PieceKind; 5] = [
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+    PieceKind::Pawn,
+];
+
+/// Font used for text and for pieces without a texture.
+pub(crate) const FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// Error returned when piece sprites can't be loaded from the override directory.
+#[derive(Debug)]
+pub enum SpriteError {
+    /// Some sprite files are missing from the directory.
+    Incomplete { dir: PathBuf, missing: Vec<&'static str> },
+    /// A sprite file exists but couldn't be decoded.
+    Unreadable { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for SpriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpriteError::Incomplete { dir, missing } => write!(
+                f,
+                "Sprite directory {} is missing: {}",
+                dir.display(),
+                missing.join(", ")
+            ),
+            SpriteError::Unreadable { path, reason } => {
+                write!(f, "Could not load sprite {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpriteError {}
+
+/// Returns the Unicode chess glyph drawn for pieces without a texture.
+pub fn piece_glyph(piece: ChessPiece) -> Option<char> {
+    match piece {
+        ChessPiece::WKing => Some('♔'),
+        ChessPiece::WQueen => Some('♕'),
+        ChessPiece::WRook => Some('♖'),
+        ChessPiece::WBishop => Some('♗'),
+        ChessPiece::WKnight => Some('♘'),
+        ChessPiece::WPawn => Some('♙'),
+        ChessPiece::BKing => Some('♚'),
+        ChessPiece::BQueen => Some('♛'),
+        ChessPiece::BRook => Some('♜'),
+        ChessPiece::BBishop => Some('♝'),
+        ChessPiece::BKnight => Some('♞'),
+        ChessPiece::BPawn => Some('♟'),
+        _ => None,
+    }
+}
 
 /// Stores chess board view settings.
 pub struct ChessGraphicsSettings {
@@ -29,6 +113,47 @@ pub struct ChessGraphicsSettings {
     pub selected_square_background_color: Color,
     /// Hovered square color
     pub hovered_square_background_color: Color,
+    /// Color of the squares of queued premoves.
+    pub premove_square_color: Color,
+    /// Colors of square marks and arrows, in the order of `AnnotationColor`.
+    pub annotation_colors: [Color; 4],
+    /// Directory to load piece sprites from instead of the embedded ones.
+    pub sprites_dir: Option<PathBuf>,
+    /// Whether pieces get an outline, so they stand out on squares of a similar color.
+    pub outlined_pieces: bool,
+    /// Color of the piece outlines.
+    pub piece_outline_color: Color,
+    /// Position of the move list from left-top corner.
+    pub move_list_offset: [f64; 2],
+    /// Width and height of the move list.
+    pub move_list_size: [f64; 2],
+    /// Height of a move list row.
+    pub move_list_row_height: f64,
+    /// Rows at the bottom of the move list taken by the engine's variations while analysing.
+    pub analysis_rows: usize,
+    /// Rows at the bottom of the move list taken by the chat in network games, the last one for
+    /// typing a message.
+    pub chat_rows: usize,
+    /// Width of the evaluation bar between the board and the move list.
+    pub eval_bar_width: f64,
+    /// Color of the arrow for the engine's best move, the other variations are fainter.
+    pub analysis_arrow_color: Color,
+    /// Move list background color.
+    pub move_list_background_color: Color,
+    /// Background color of the move leading to the position shown.
+    pub viewed_move_color: Color,
+    /// Text color.
+    pub text_color: Color,
+    /// Size of the captured pieces shown above and below the board.
+    pub captured_piece_size: f64,
+    /// Height of the status bar along the bottom of the window.
+    pub status_bar_height: f64,
+    /// Status bar background color.
+    pub status_bar_color: Color,
+    /// Position of the first start menu entry from left-top corner.
+    pub menu_offset: [f64; 2],
+    /// Width and height of a start menu entry.
+    pub menu_item_size: [f64; 2],
 }
 
 impl ChessGraphicsSettings {
@@ -46,149 +171,360 @@ impl ChessGraphicsSettings {
             black_color: [0.30, 0.15, 0.15, 1.0],
             selected_square_background_color: [0.3, 0.3, 0.5, 1.0],
             hovered_square_background_color: [0.7, 0.75, 0.9, 1.0],
+            premove_square_color: [0.75, 0.3, 0.3, 1.0],
+            annotation_colors: [
+                [0.1, 0.6, 0.2, 0.7],
+                [0.8, 0.1, 0.1, 0.7],
+                [0.1, 0.4, 0.8, 0.7],
+                [0.9, 0.7, 0.0, 0.7],
+            ],
+            sprites_dir: None,
+            outlined_pieces: false,
+            piece_outline_color: [0.0, 0.0, 0.0, 1.0],
+            move_list_offset: [size + 80.0, 50.0],
+            move_list_size: [200.0, size],
+            move_list_row_height: 20.0,
+            analysis_rows: ANALYSIS_LINES + 1,
+            chat_rows: 8,
+            eval_bar_width: 14.0,
+            analysis_arrow_color: [0.9, 0.45, 0.1, 0.8],
+            move_list_background_color: [0.25, 0.25, 0.25, 1.0],
+            viewed_move_color: [0.3, 0.3, 0.5, 1.0],
+            text_color: [0.95, 0.95, 0.95, 1.0],
+            captured_piece_size: 28.0,
+            status_bar_height: 30.0,
+            status_bar_color: [0.15, 0.15, 0.15, 1.0],
+            menu_offset: [150.0, 150.0],
+            menu_item_size: [500.0, 60.0],
         }
     }
 
-}
+    /// Creates settings for a high-contrast theme, where light and dark squares differ in
+    /// brightness rather than hue and pieces are outlined.
+    pub fn high_contrast() -> ChessGraphicsSettings {
+        ChessGraphicsSettings {
+            white_color: [1.0, 1.0, 1.0, 1.0],
+            black_color: [0.45, 0.45, 0.45, 1.0],
+            selected_square_background_color: [1.0, 0.85, 0.0, 1.0],
+            hovered_square_background_color: [0.0, 0.8, 1.0, 1.0],
+            premove_square_color: [1.0, 0.0, 1.0, 1.0],
+            annotation_colors: [
+                [0.0, 0.8, 0.0, 0.9],
+                [1.0, 0.0, 0.0, 0.9],
+                [0.0, 0.3, 1.0, 0.9],
+                [1.0, 0.85, 0.0, 0.9],
+            ],
+            outlined_pieces: true,
+            move_list_background_color: [0.0, 0.0, 0.0, 1.0],
+            viewed_move_color: [0.0, 0.3, 0.8, 1.0],
+            text_color: [1.0, 1.0, 1.0, 1.0],
+            status_bar_color: [0.0, 0.0, 0.0, 1.0],
+            ..ChessGraphicsSettings::new()
+        }
+    }
 
-/// Stores visual information about a chess board.
-pub struct ChessGraphics {
-    /// Stores chess board view settings.
-    pub settings: ChessGraphicsSettings,
-    pub textures: HashMap<ChessPiece, Texture>,
-}
+    /// Returns the rectangle of a board index, with rank 1 at the bottom.
+    pub fn square_rect(&self, square: u8) -> [f64; 4] {
+        [
+            self.offset[0] + self.square_side * (square % 8) as f64,
+            self.offset[1] + self.square_side * (7 - square / 8) as f64,
+            self.square_side,
+            self.square_side,
+        ]
+    }
 
-impl ChessGraphics {
-    /// Creates a new chess board view.
-    pub fn new(settings: ChessGraphicsSettings) -> ChessGraphics {
-        let textures = Self::get_textures();
+    /// Returns the center of a board index.
+    pub fn square_center(&self, square: u8) -> [f64; 2] {
+        let [x, y, side, _] = self.square_rect(square);
+        [x + side / 2.0, y + side / 2.0]
+    }
 
-        ChessGraphics {
-            settings,
-            textures,
+    /// Returns whether a board index is a light square.
+    pub fn is_light_square(&self, square: u8) -> bool {
+        (square % 8 + square / 8) % 2 == 1
+    }
+
+    /// Returns the window size that fits the board, the move list and the status bar.
+    pub fn window_size(&self) -> [f64; 2] {
+        [
+            self.move_list_offset[0] + self.move_list_size[0] + self.offset[0],
+            self.size + self.offset[1] * 2.0 + self.status_bar_height,
+        ]
+    }
+
+    /// Returns how many rows of the move list fit on screen, fewer while analysing and when there
+    /// is a chat.
+    pub fn move_list_rows(&self, analysing: bool, chatting: bool) -> usize {
+        let mut rows = (self.move_list_size[1] / self.move_list_row_height) as usize;
+        if analysing {
+            rows = rows.saturating_sub(self.analysis_rows);
         }
+        if chatting {
+            rows = rows.saturating_sub(self.chat_rows);
+        }
+        rows
     }
 
-    fn get_textures() -> HashMap<ChessPiece, Texture> {
-        let mut textures = HashMap::new();
+    /// Returns the rectangle of the chat at the bottom of the move list, including the row for
+    /// typing a message.
+    pub fn chat_rect(&self) -> [f64; 4] {
+        let height = self.move_list_row_height * self.chat_rows as f64;
+        [
+            self.move_list_offset[0],
+            self.move_list_offset[1] + self.move_list_size[1] - height,
+            self.move_list_size[0],
+            height,
+        ]
+    }
 
-        // TODO: Fix duplication
-        // White Pieces
+    /// Returns the rectangle of the row for typing a chat message.
+    pub fn chat_input_rect(&self) -> [f64; 4] {
+        let [x, y, width, height] = self.chat_rect();
+        [x, y + height - self.move_list_row_height, width, self.move_list_row_height]
+    }
 
-        textures.insert(ChessPiece::WPawn,
-                        Texture::from_path(Path::new("sprites/w_pawn.png"),
-                                                              &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of a move in the move list, `row` counting down from the first
+    /// row shown and `column` 0 for White's move and 1 for Black's.
+    pub fn move_list_cell_rect(&self, row: usize, column: usize) -> [f64; 4] {
+        let column_width = (self.move_list_size[0] - 40.0) / 2.0;
+        [
+            self.move_list_offset[0] + 40.0 + column_width * column as f64,
+            self.move_list_offset[1] + self.move_list_row_height * row as f64,
+            column_width,
+            self.move_list_row_height,
+        ]
+    }
 
-        textures.insert(ChessPiece::WBishop,
-                        Texture::from_path(Path::new("sprites/w_bishop.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of the evaluation bar, centered in the gap between the board and the
+    /// move list.
+    pub fn eval_bar_rect(&self) -> [f64; 4] {
+        let gap = self.move_list_offset[0] - (self.offset[0] + self.size);
+        [
+            self.offset[0] + self.size + (gap - self.eval_bar_width) / 2.0,
+            self.offset[1],
+            self.eval_bar_width,
+            self.size,
+        ]
+    }
 
-        textures.insert(ChessPiece::WKnight,
-                        Texture::from_path(Path::new("sprites/w_knight.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the board index of the square at `pos`, if it is on the board.
+    pub fn square_at(&self, pos: [f64; 2]) -> Option<u8> {
+        let x = pos[0] - self.offset[0];
+        let y = pos[1] - self.offset[1];
+        if x >= 0.0 && x < self.size && y >= 0.0 && y < self.size {
+            let coords_x = (x / self.size * self.square_amount) as u8;
+            let coords_y = (y / self.size * self.square_amount) as u8;
+            Some(coords_x + (7 - coords_y) * 8)
+        } else {
+            None
+        }
+    }
 
-        textures.insert(ChessPiece::WRook,
-                        Texture::from_path(Path::new("sprites/w_rook.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of a piece in the board editor's palette, which takes the place of
+    /// the move list with six pieces per row.
+    pub fn palette_rect(&self, index: usize) -> [f64; 4] {
+        let side = self.move_list_size[0] / 6.0;
+        [
+            self.move_list_offset[0] + side * (index % 6) as f64,
+            self.move_list_offset[1] + side * (index / 6) as f64,
+            side,
+            side,
+        ]
+    }
 
-        textures.insert(ChessPiece::WQueen,
-                        Texture::from_path(Path::new("sprites/w_queen.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of a board editor button, in a column below the palette.
+    pub fn editor_button_rect(&self, index: usize) -> [f64; 4] {
+        let height = self.move_list_row_height * 1.5;
+        let [_, palette_y, _, palette_side] = self.palette_rect(0);
+        [
+            self.move_list_offset[0],
+            palette_y + palette_side * 2.0 + 10.0 + height * index as f64,
+            self.move_list_size[0],
+            height - 4.0,
+        ]
+    }
 
-        textures.insert(ChessPiece::WKing,
-                        Texture::from_path(Path::new("sprites/w_king.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the index of the first rectangle containing `pos`, if any.
+    pub fn rect_at(&self, pos: [f64; 2], rects: impl Iterator<Item = [f64; 4]>) -> Option<usize> {
+        rects
+            .enumerate()
+            .find(|(_, [x, y, width, height])| {
+                pos[0] >= *x && pos[0] < x + width && pos[1] >= *y && pos[1] < y + height
+            })
+            .map(|(index, _)| index)
+    }
 
-        // Black pieces
+    /// Returns how many games fit in the game chooser.
+    pub fn chooser_rows(&self) -> usize {
+        let row_height = self.menu_item_size[1] / 2.0;
+        let [_, height] = self.window_size();
+        ((height - self.menu_offset[1] - row_height * 3.0) / row_height) as usize
+    }
 
-        textures.insert(ChessPiece::BPawn,
-                        Texture::from_path(Path::new("sprites/b_pawn.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of a row of the game chooser, counted from the first row shown.
+    pub fn chooser_row_rect(&self, row: usize) -> [f64; 4] {
+        let row_height = self.menu_item_size[1] / 2.0;
+        [
+            self.menu_offset[0] - self.offset[0],
+            self.menu_offset[1] + row_height * row as f64,
+            self.menu_item_size[0] + self.offset[0] * 2.0,
+            row_height - 4.0,
+        ]
+    }
 
-        textures.insert(ChessPiece::BBishop,
-                        Texture::from_path(Path::new("sprites/b_bishop.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the index of the start menu entry at `pos`, if any.
+    pub fn menu_item_at(&self, pos: [f64; 2], item_amount: usize) -> Option<usize> {
+        let x = pos[0] - self.menu_offset[0];
+        let y = pos[1] - self.menu_offset[1];
+        let index = (y / self.menu_item_size[1]).floor();
+        if x >= 0.0 && x < self.menu_item_size[0] && index >= 0.0 && (index as usize) < item_amount {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
 
-        textures.insert(ChessPiece::BKnight,
-                        Texture::from_path(Path::new("sprites/b_knight.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of the cancel button on the waiting screen.
+    pub fn cancel_button_rect(&self) -> [f64; 4] {
+        [
+            self.menu_offset[0],
+            self.menu_offset[1] + self.menu_item_size[1] * 2.0,
+            self.menu_item_size[0] / 3.0,
+            self.menu_item_size[1] - 8.0,
+        ]
+    }
 
-        textures.insert(ChessPiece::BRook,
-                        Texture::from_path(Path::new("sprites/b_rook.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns whether `pos` is on the cancel button of the waiting screen.
+    pub fn cancel_button_contains(&self, pos: [f64; 2]) -> bool {
+        let [x, y, width, height] = self.cancel_button_rect();
+        pos[0] >= x && pos[0] < x + width && pos[1] >= y && pos[1] < y + height
+    }
 
-        textures.insert(ChessPiece::BQueen,
-                        Texture::from_path(Path::new("sprites/b_queen.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of the takeback prompt, across the middle of the board.
+    pub fn takeback_prompt_rect(&self) -> [f64; 4] {
+        [
+            self.offset[0] + self.square_side * 0.5,
+            self.offset[1] + self.size / 2.0 - self.square_side,
+            self.size - self.square_side,
+            self.square_side * 2.0,
+        ]
+    }
 
-        textures.insert(ChessPiece::BKing,
-                        Texture::from_path(Path::new("sprites/b_king.png"),
-                                           &TextureSettings::new()).unwrap()
-        );
+    /// Returns the rectangle of a button of the takeback prompt, accept first.
+    pub fn takeback_button_rect(&self, index: usize) -> [f64; 4] {
+        let [x, y, width, height] = self.takeback_prompt_rect();
+        [
+            x + width / 2.0 * index as f64 + 12.0,
+            y + height / 2.0,
+            width / 2.0 - 24.0,
+            height / 2.0 - 12.0,
+        ]
+    }
+
+    /// Returns whether `pos` is inside the move list.
+    pub fn move_list_contains(&self, pos: [f64; 2]) -> bool {
+        let x = pos[0] - self.move_list_offset[0];
+        let y = pos[1] - self.move_list_offset[1];
+        x >= 0.0 && x < self.move_list_size[0] && y >= 0.0 && y < self.move_list_size[1]
+    }
+}
+
+/// Stores visual information about a chess board.
+///
+/// `T` is the texture type of the graphics backend drawn to, e.g. `opengl_graphics::Texture` for
+/// the window or `SoftwareTexture` for drawing into an image without a GPU.
+pub struct ChessGraphics<T> {
+    /// Stores chess board view settings.
+    pub settings: ChessGraphicsSettings,
+    pub textures: HashMap<ChessPiece, T>,
+    /// Glyphs for text, also used to draw pieces that have no texture.
+    glyphs: RefCell<GlyphCache<'static, (), T>>,
+}
+
+impl<T> ChessGraphics<T>
+where
+    T: ImageSize + CreateTexture<()> + UpdateTexture<()>,
+{
+    /// Creates a new chess board view.
+    pub fn new(settings: ChessGraphicsSettings) -> Result<ChessGraphics<T>, SpriteError> {
+        let textures = match &settings.sprites_dir {
+            Some(dir) => Self::load_textures(dir.clone())?,
+            None => Self::embedded_textures(),
+        };
+        let glyphs = GlyphCache::from_bytes(FONT, (), TextureSettings::new())
+            .expect("Embedded font is invalid");
+
+        Ok(ChessGraphics {
+            settings,
+            textures,
+            glyphs: RefCell::new(glyphs),
+        })
+    }
+
+    /// Decodes the sprites embedded in the binary.
+    ///
+    /// A sprite that fails to decode is left out and drawn as a glyph instead.
+    fn embedded_textures() -> HashMap<ChessPiece, T> {
+        let mut textures = HashMap::new();
+
+        for (piece, file_name, bytes) in SPRITES {
+            let image = image::load_from_memory(bytes).map_err(|e| e.to_string());
+            match image.and_then(|image| Self::create_texture(image.to_rgba8())) {
+                Ok(texture) => {
+                    textures.insert(piece, texture);
+                }
+                Err(e) => eprintln!("Could not decode embedded sprite {}: {}", file_name, e),
+            }
+        }
 
         textures
+    }
+
+    /// Loads every sprite from `dir`, which must contain the same file names as `sprites/`.
+    fn load_textures(dir: PathBuf) -> Result<HashMap<ChessPiece, T>, SpriteError> {
+        let missing: Vec<&'static str> = SPRITES
+            .iter()
+            .map(|(_, file_name, _)| *file_name)
+            .filter(|file_name| !dir.join(file_name).is_file())
+            .collect();
+        if !missing.is_empty() {
+            return Err(SpriteError::Incomplete { dir, missing });
+        }
+
+        let mut textures = HashMap::new();
+
+        for (piece, file_name, _) in SPRITES {
+            let path = dir.join(file_name);
+            let texture = image::open(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|image| Self::create_texture(image.to_rgba8()))
+                .map_err(|reason| SpriteError::Unreadable { path, reason })?;
+            textures.insert(piece, texture);
+        }
+
+        Ok(textures)
+    }
 
+    /// Uploads decoded pixels to a texture of the graphics backend.
+    fn create_texture(image: RgbaImage) -> Result<T, String> {
+        let (width, height) = image.dimensions();
+        T::create(&mut (), Format::Rgba8, image.as_raw(), [width, height], &TextureSettings::new())
+            .map_err(|e| format!("{:?}", e))
     }
 
     /// Draw chess board.
-    pub fn draw<G: Graphics<Texture = Texture>>(
+    pub fn draw<G: Graphics<Texture = T>>(
         &self,
         controller: &ChessController,
         c: &Context,
         g: &mut G,
     ) {
-        let ref settings = self.settings;
-        let ref board = controller.chess_engine.get_board();
-
-        // TODO: Ranks and flanks
-        // let mut glyphs = Glyphs::from_bytes(
-        //     font,
-        //     window.create_texture_context(),
-        //     TextureSettings::new(),
-        // );
-
-        // Text::new_color([0.0, 0.0, 0.0, 1.0], 32).draw(
-        //     "test",
-        //     &mut glyphs,
-        //     &c.draw_state,
-        //     c.transform,
-        //     g
-        // );
-
-        // Draw board
-        for x in 0..=7 {
-            for y in 0..=7 {
-                let square_rect = [
-                    settings.offset[0] + settings.square_side * x as f64,
-                    settings.offset[1] + settings.square_side * (7 - y) as f64,
-                    settings.square_side,
-                    settings.square_side,
-                ];
-
-                let mut color = settings.black_color;
-                if x % 2 + y % 2 == 1 {
-                    color = settings.white_color;
-                }
+        let settings = &self.settings;
+        let board = &controller.game.position.board;
 
-                Rectangle::new(color).draw(
-                    square_rect,
-                    &c.draw_state,
-                    c.transform,
-                    g,
-                );
-            }
-        }
+        // TODO: Ranks and files
+
+        self.draw_squares(c, g);
 
         // TODO: Fix duplication
         if let Some(square) = controller.hovered_square {
@@ -234,24 +570,615 @@ impl ChessGraphics {
             );
         }
 
-        for x in 0..=7 {
-            for y in 0..=7 {
-                let square_rect = [
-                    settings.offset[0] + settings.square_side * x as f64,
-                    settings.offset[1] + settings.square_side * (7 - y) as f64,
-                    settings.square_side,
-                    settings.square_side,
-                ];
-
-                let piece = board[(x + y * 8) as usize];
-                // println!("{:?} {}", piece, x + y * 8);
-                if !(piece == ChessPiece::Empty) {
-                    // println!("{:?}", piece);
-                    let piece_texture = self.textures.get(&piece).unwrap();
-                    let square_drawable = Image::new().rect(square_rect);
-                    square_drawable.draw(piece_texture.clone(), &c.draw_state, c.transform, g);
+        for premove in &controller.game.premoves {
+            for square in [premove.from, premove.to] {
+                Rectangle::new(settings.premove_square_color).draw(
+                    settings.square_rect(square),
+                    &c.draw_state,
+                    c.transform,
+                    g,
+                );
+            }
+        }
+
+        self.draw_pieces(board, c, g);
+
+        if let Some(analysis) = &controller.game.analysis {
+            self.draw_analysis_arrows(analysis, c, g);
+        }
+        self.draw_annotations(&controller.annotations, c, g);
+
+        let captured = controller.game.captured_pieces();
+        let mut on_board = HashMap::new();
+        for piece in board.iter() {
+            *on_board.entry(*piece).or_insert(0) += 1;
+        }
+        self.draw_captured_pieces(Side::White, &captured, &on_board, c, g);
+        self.draw_captured_pieces(Side::Black, &captured, &on_board, c, g);
+
+        self.draw_move_list(controller, c, g);
+        if let Some(analysis) = &controller.game.analysis {
+            self.draw_analysis(analysis, controller.game.has_chat(), c, g);
+        }
+        if controller.game.has_chat() {
+            self.draw_chat(controller, c, g);
+        }
+        self.draw_status_bar(&controller.game.status_text(), &controller.move_input, c, g);
+
+        if let Some(prompt) = controller.game.takeback_prompt() {
+            self.draw_takeback_prompt(&prompt, c, g);
+        }
+    }
+
+    /// Draw the board editor, with the palette and buttons where the move list is.
+    pub fn draw_editor<G: Graphics<Texture = T>>(
+        &self,
+        editor: &BoardEditor,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        self.draw_squares(c, g);
+
+        if let Some(square) = editor.hovered_square {
+            Rectangle::new(settings.hovered_square_background_color).draw(
+                settings.square_rect(square),
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+        self.draw_pieces(&editor.position.board, c, g);
+
+        // The en passant square is empty, so it is marked with a ring like an annotation
+        if let Some(square) = editor.position.en_passant {
+            let [x, y, side, _] = settings.square_rect(square);
+            Ellipse::new_border(settings.annotation_colors[0], side * 0.05).draw(
+                [x + side * 0.05, y + side * 0.05, side * 0.9, side * 0.9],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+
+        for (index, piece) in PALETTE.iter().enumerate() {
+            let rect = settings.palette_rect(index);
+            let color = if *piece == editor.selected_piece {
+                settings.selected_square_background_color
+            } else {
+                settings.move_list_background_color
+            };
+            Rectangle::new(color).draw(rect, &c.draw_state, c.transform, g);
+            self.draw_piece(*piece, rect, c, g);
+        }
+
+        let font_size = (settings.move_list_row_height * 0.7) as u32;
+        for (index, button) in EDITOR_BUTTONS.iter().enumerate() {
+            let [x, y, width, height] = settings.editor_button_rect(index);
+            Rectangle::new(settings.move_list_background_color).draw(
+                [x, y, width, height],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+            let baseline = y + height * 0.7;
+            self.draw_text(&editor.button_label(*button), settings.text_color, font_size, [x + 6.0, baseline], c, g);
+        }
+
+        self.draw_status_bar(&editor.status_text(), "", c, g);
+    }
+
+    /// Draws the light and dark squares of the board.
+    fn draw_squares<G: Graphics<Texture = T>>(
+        &self,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        for square in 0..64 {
+            let mut color = settings.black_color;
+            if settings.is_light_square(square) {
+                color = settings.white_color;
+            }
+
+            Rectangle::new(color).draw(
+                settings.square_rect(square),
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+    }
+
+    /// Draws the pieces of `board` on their squares.
+    fn draw_pieces<G: Graphics<Texture = T>>(
+        &self,
+        board: &[ChessPiece; 64],
+        c: &Context,
+        g: &mut G,
+    ) {
+        for square in 0..64u8 {
+            let piece = board[square as usize];
+            self.draw_piece(piece, self.settings.square_rect(square), c, g);
+        }
+    }
+
+    /// Draw start menu.
+    pub fn draw_menu<G: Graphics<Texture = T>>(
+        &self,
+        menu: &StartMenu,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let [x, y] = settings.menu_offset;
+        let [width, height] = settings.menu_item_size;
+        let font_size = (height * 0.35) as u32;
+
+        self.draw_text("Chess", settings.text_color, font_size * 2, [x, y - height], c, g);
+
+        for (index, item) in menu.items.iter().enumerate() {
+            let item_y = y + height * index as f64;
+            let color = if index == menu.selected {
+                settings.selected_square_background_color
+            } else {
+                settings.move_list_background_color
+            };
+            Rectangle::new(color).draw(
+                [x, item_y, width, height - 8.0],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+
+            let baseline = item_y + height * 0.55;
+            self.draw_text(item.label(), settings.text_color, font_size, [x + 12.0, baseline], c, g);
+
+            if let (Some(name), Some(field)) = (item.field_name(), menu.field(*item)) {
+                // Show a cursor in the field being edited
+                let cursor = if index == menu.selected { "|" } else { "" };
+                let text = format!("{}: {}{}", name, field, cursor);
+                self.draw_text(&text, settings.text_color, font_size, [x + width * 0.4, baseline], c, g);
+            }
+        }
+
+        let below_items = y + height * menu.items.len() as f64 + height * 0.5;
+        let hint = "Up/Down or click to choose, type to edit, Enter or click again to start";
+        self.draw_text(hint, settings.text_color, font_size * 2 / 3, [x, below_items], c, g);
+        if let Some(message) = &menu.message {
+            self.draw_text(message, [1.0, 0.5, 0.5, 1.0], font_size * 2 / 3, [x, below_items + height * 0.5], c, g);
+        }
+    }
+
+    /// Draw the chooser for a PGN file with several games.
+    pub fn draw_chooser<G: Graphics<Texture = T>>(
+        &self,
+        chooser: &GameChooser,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let [x, y] = settings.menu_offset;
+        let font_size = (settings.menu_item_size[1] * 0.35) as u32;
+
+        self.draw_text("Choose a game", settings.text_color, font_size * 2, [x, y - settings.menu_item_size[1]], c, g);
+
+        let first = chooser.first_shown(settings);
+        let rows = settings.chooser_rows();
+        for (row, game) in chooser.games.iter().enumerate().skip(first).take(rows) {
+            let rect = settings.chooser_row_rect(row - first);
+            let color = if row == chooser.selected {
+                settings.selected_square_background_color
+            } else {
+                settings.move_list_background_color
+            };
+            Rectangle::new(color).draw(rect, &c.draw_state, c.transform, g);
+
+            let text = format!("{}. {}", row + 1, game.description());
+            self.draw_text(&text, settings.text_color, font_size * 2 / 3, [rect[0] + 8.0, rect[1] + rect[3] * 0.7], c, g);
+        }
+
+        let [rect_x, rect_y, _, rect_height] = settings.chooser_row_rect(rows);
+        let hint = format!("{} games, Up/Down or click to choose, Enter or click again to replay", chooser.games.len());
+        self.draw_text(&hint, settings.text_color, font_size * 2 / 3, [rect_x, rect_y + rect_height], c, g);
+    }
+
+    /// Draw the screen shown while waiting for a network game to connect.
+    pub fn draw_waiting<G: Graphics<Texture = T>>(
+        &self,
+        waiting: &WaitingScreen,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let [x, y] = settings.menu_offset;
+        let font_size = (settings.menu_item_size[1] * 0.35) as u32;
+
+        self.draw_text(&waiting.description, settings.text_color, font_size, [x, y + settings.menu_item_size[1] * 0.5], c, g);
+
+        let button = settings.cancel_button_rect();
+        Rectangle::new(settings.move_list_background_color).draw(
+            button,
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+        self.draw_text("Cancel", settings.text_color, font_size, [button[0] + 12.0, button[1] + button[3] * 0.62], c, g);
+    }
+
+    /// Draws the opponent's takeback request over the board, with buttons to answer it.
+    fn draw_takeback_prompt<G: Graphics<Texture = T>>(
+        &self,
+        prompt: &str,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let rect = settings.takeback_prompt_rect();
+        Rectangle::new(settings.status_bar_color).draw(rect, &c.draw_state, c.transform, g);
+
+        let font_size = (settings.status_bar_height * 0.5) as u32;
+        self.draw_text(prompt, settings.text_color, font_size, [rect[0] + 12.0, rect[1] + rect[3] * 0.3], c, g);
+
+        for (index, label) in ["Accept (Y)", "Decline (N)"].iter().enumerate() {
+            let button = settings.takeback_button_rect(index);
+            Rectangle::new(settings.move_list_background_color).draw(button, &c.draw_state, c.transform, g);
+            self.draw_text(label, settings.text_color, font_size, [button[0] + 12.0, button[1] + button[3] * 0.65], c, g);
+        }
+    }
+
+    /// Draws `status`, e.g. the connection and game state, along the bottom of the window, and the
+    /// move being typed if there is one.
+    fn draw_status_bar<G: Graphics<Texture = T>>(
+        &self,
+        status: &str,
+        move_input: &str,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let [width, height] = settings.window_size();
+        let bar_height = settings.status_bar_height;
+        let y = height - bar_height;
+
+        Rectangle::new(settings.status_bar_color).draw(
+            [0.0, y, width, bar_height],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+
+        let font_size = (bar_height * 0.5) as u32;
+        let baseline = y + bar_height * 0.7;
+        self.draw_text(status, settings.text_color, font_size, [settings.offset[0], baseline], c, g);
+
+        // The move being typed goes where the move list is, out of the way of the status text
+        if !move_input.is_empty() {
+            let text = format!("Move: {}|", move_input);
+            self.draw_text(&text, settings.text_color, font_size, [settings.move_list_offset[0], baseline], c, g);
+        }
+    }
+
+    /// Draws marked squares as rings and arrows between square centers over the pieces.
+    fn draw_annotations<G: Graphics<Texture = T>>(
+        &self,
+        annotations: &Annotations,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let side = settings.square_side;
+        let color = |color: AnnotationColor| settings.annotation_colors[color as usize];
+
+        for (square, mark_color) in &annotations.squares {
+            let [x, y, _, _] = settings.square_rect(*square);
+            Ellipse::new_border(color(*mark_color), side * 0.05).draw(
+                [x + side * 0.05, y + side * 0.05, side * 0.9, side * 0.9],
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+
+        for arrow in &annotations.arrows {
+            let [from_x, from_y] = settings.square_center(arrow.from);
+            let [to_x, to_y] = settings.square_center(arrow.to);
+            Line::new_round(color(arrow.color), side * 0.08).draw_arrow(
+                [from_x, from_y, to_x, to_y],
+                side * 0.3,
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+    }
+
+    /// Draws the pieces `side` has captured and its material lead, below the board for White and
+    /// above it for Black.
+    ///
+    /// `captured` holds the pieces both sides have captured, and the lead is counted from
+    /// `on_board`, the amount of each piece on the board.
+    fn draw_captured_pieces<G: Graphics<Texture = T>>(
+        &self,
+        side: Side,
+        captured: &[ChessPiece],
+        on_board: &HashMap<ChessPiece, usize>,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let piece_size = settings.captured_piece_size;
+        let margin = (settings.offset[1] - piece_size) / 2.0;
+        let y = match side {
+            Side::White => settings.offset[1] + settings.size + margin,
+            Side::Black => margin,
+        };
+        let count = |piece: ChessPiece| on_board.get(&piece).copied().unwrap_or(0);
+        let mut x = settings.offset[0];
+
+        for kind in CAPTURABLE_KINDS {
+            let piece = make_piece(side.opponent(), kind);
+            for _ in captured.iter().filter(|captured| **captured == piece) {
+                self.draw_piece(piece, [x, y, piece_size, piece_size], c, g);
+                // Overlap pieces of the same kind to save space
+                x += piece_size * 0.6;
+            }
+            x += piece_size * 0.2;
+        }
+
+        // Count the pieces on the board rather than the captures, so promotions are included
+        let material = |side: Side| -> u32 {
+            CAPTURABLE_KINDS
+                .iter()
+                .map(|kind| kind.value() * count(make_piece(side, *kind)) as u32)
+                .sum()
+        };
+        let lead = material(side) as i64 - material(side.opponent()) as i64;
+        if lead > 0 {
+            let font_size = (piece_size * 0.6) as u32;
+            let baseline = y + piece_size * 0.75;
+            self.draw_text(&format!("+{}", lead), settings.text_color, font_size, [x + piece_size * 0.4, baseline], c, g);
+        }
+    }
+
+    /// Draws the moves played so far, one full move per row.
+    fn draw_move_list<G: Graphics<Texture = T>>(
+        &self,
+        controller: &ChessController,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let [x, y] = settings.move_list_offset;
+        let [width, height] = settings.move_list_size;
+        let row_height = settings.move_list_row_height;
+        let font_size = (row_height * 0.7) as u32;
+
+        Rectangle::new(settings.move_list_background_color).draw(
+            [x, y, width, height],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+
+        // Scrolling moves the window of visible rows up from the latest move
+        let moves = controller.move_list();
+        let rows = controller.move_list_row_count();
+        let visible_rows = controller.move_list_rows(settings);
+        let first_row = controller.move_list_first_row(settings);
+        let viewed = controller.game.history.len();
+        // White's cell of the first row is empty when Black moved first
+        let offset = controller.game.first_move_offset();
+
+        for row in first_row..rows.min(first_row + visible_rows) {
+            let baseline = y + (row - first_row + 1) as f64 * row_height - row_height * 0.25;
+
+            let number = controller.game.start.fullmove_number as usize + row;
+            self.draw_text(&format!("{}.", number), settings.text_color, font_size, [x + 6.0, baseline], c, g);
+            for column in 0..2 {
+                let [column_x, ..] = settings.move_list_cell_rect(row - first_row, column);
+                let index = match (row * 2 + column).checked_sub(offset) {
+                    Some(index) if index < moves.len() => index,
+                    Some(_) => continue,
+                    None => {
+                        self.draw_text("...", settings.text_color, font_size, [column_x, baseline], c, g);
+                        continue;
+                    }
+                };
+                let san = moves[index];
+                // Highlight the move leading to the position shown, when moves follow it
+                if index + 1 == viewed && viewed < moves.len() {
+                    Rectangle::new(settings.viewed_move_color).draw(
+                        settings.move_list_cell_rect(row - first_row, column),
+                        &c.draw_state,
+                        c.transform,
+                        g,
+                    );
                 }
+                self.draw_text(san, settings.text_color, font_size, [column_x, baseline], c, g);
+            }
+        }
+    }
+
+    /// Draws arrows for the first move of each of the engine's variations, fainter for worse ones.
+    fn draw_analysis_arrows<G: Graphics<Texture = T>>(
+        &self,
+        analysis: &Analysis,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let side = settings.square_side;
+
+        // Drawn from the worst variation so the best arrow ends up on top
+        for (rank, line) in analysis.lines.iter().enumerate().rev() {
+            let chess_move = line.moves[0];
+            let [from_x, from_y] = settings.square_center(chess_move.from);
+            let [to_x, to_y] = settings.square_center(chess_move.to);
+            let mut color = settings.analysis_arrow_color;
+            color[3] /= (rank + 1) as f32;
+            Line::new_round(color, side * 0.06).draw_arrow(
+                [from_x, from_y, to_x, to_y],
+                side * 0.25,
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+    }
+
+    /// Draws the evaluation bar and the engine's variations below the move list, above the chat
+    /// if there is one.
+    fn draw_analysis<G: Graphics<Texture = T>>(
+        &self,
+        analysis: &Analysis,
+        chatting: bool,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+
+        // White's share fills the bar from the bottom, like White's side of the board
+        let [x, y, width, height] = settings.eval_bar_rect();
+        let white_share = analysis.score().map_or(0.5, |score| score.white_share());
+        Rectangle::new(settings.status_bar_color).draw([x, y, width, height], &c.draw_state, c.transform, g);
+        Rectangle::new(settings.text_color).draw(
+            [x, y + height * (1.0 - white_share), width, height * white_share],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+
+        let [list_x, list_y] = settings.move_list_offset;
+        let [list_width, list_height] = settings.move_list_size;
+        let row_height = settings.move_list_row_height;
+        let font_size = (row_height * 0.7) as u32;
+        let chat_rows = if chatting { settings.chat_rows } else { 0 };
+        let top = list_y + list_height - (settings.analysis_rows + chat_rows) as f64 * row_height;
+        Line::new(settings.text_color, 0.5).draw(
+            [list_x, top, list_x + list_width, top],
+            &c.draw_state,
+            c.transform,
+            g,
+        );
+
+        let header = match analysis.depth {
+            0 => String::from("Analysing..."),
+            depth => format!("Depth {}", depth),
+        };
+        let baseline = |row: usize| top + (row + 1) as f64 * row_height - row_height * 0.25;
+        self.draw_text(&header, settings.text_color, font_size, [list_x + 6.0, baseline(0)], c, g);
+
+        for (rank, line) in analysis.lines.iter().enumerate().take(settings.analysis_rows - 1) {
+            // Only the first moves fit next to the score
+            let moves = line.san.iter().take(5).cloned().collect::<Vec<_>>().join(" ");
+            let text = format!("{}  {}", line.score, moves);
+            self.draw_text(&text, settings.text_color, font_size, [list_x + 6.0, baseline(rank + 1)], c, g);
+        }
+    }
+
+    /// Draws the latest chat messages that fit, scrolled back by the controller, and the message
+    /// being typed.
+    fn draw_chat<G: Graphics<Texture = T>>(
+        &self,
+        controller: &ChessController,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let settings = &self.settings;
+        let [x, y, width, _] = settings.chat_rect();
+        let row_height = settings.move_list_row_height;
+        let font_size = (row_height * 0.7) as u32;
+        Line::new(settings.text_color, 0.5).draw([x, y, x + width, y], &c.draw_state, c.transform, g);
+
+        let chat = &controller.game.chat;
+        let visible_rows = settings.chat_rows - 1;
+        let end = chat.len().saturating_sub(controller.chat_scroll);
+        let baseline = |row: usize| y + (row + 1) as f64 * row_height - row_height * 0.25;
+        for (row, line) in chat[end.saturating_sub(visible_rows)..end].iter().enumerate() {
+            let text = format!("{}: {}", line.sender, line.text);
+            self.draw_text(&text, settings.text_color, font_size, [x + 6.0, baseline(row)], c, g);
+        }
+
+        // The input row is highlighted while typing goes to the chat
+        let input_rect = settings.chat_input_rect();
+        let (color, text) = match &controller.chat_input {
+            Some(text) => (settings.viewed_move_color, format!("> {}_", text)),
+            None => (settings.status_bar_color, String::from("Tab to chat")),
+        };
+        Rectangle::new(color).draw(input_rect, &c.draw_state, c.transform, g);
+        self.draw_text(&text, settings.text_color, font_size, [x + 6.0, baseline(visible_rows)], c, g);
+    }
+
+    /// Draws `text` with its baseline starting at `pos`.
+    fn draw_text<G: Graphics<Texture = T>>(
+        &self,
+        text: &str,
+        color: Color,
+        font_size: u32,
+        pos: [f64; 2],
+        c: &Context,
+        g: &mut G,
+    ) {
+        let _ = Text::new_color(color, font_size).draw(
+            text,
+            &mut *self.glyphs.borrow_mut(),
+            &c.draw_state,
+            c.transform.trans(pos[0], pos[1]),
+            g,
+        );
+    }
+
+    /// Draws a piece into `rect`, falling back to its glyph when there is no texture.
+    pub fn draw_piece<G: Graphics<Texture = T>>(
+        &self,
+        piece: ChessPiece,
+        rect: [f64; 4],
+        c: &Context,
+        g: &mut G,
+    ) {
+        if piece == ChessPiece::Empty {
+            return;
+        }
+
+        // Outlines are the piece drawn in the outline color, shifted in every direction
+        let settings = &self.settings;
+        let outline = rect[2] * 0.03;
+        let outline_offsets: &[[f64; 2]] = if settings.outlined_pieces {
+            &[[-1.0, -1.0], [0.0, -1.0], [1.0, -1.0], [-1.0, 0.0], [1.0, 0.0], [-1.0, 1.0], [0.0, 1.0], [1.0, 1.0]]
+        } else {
+            &[]
+        };
+
+        if let Some(piece_texture) = self.textures.get(&piece) {
+            for [dx, dy] in outline_offsets {
+                // Tinting with the outline color turns the sprite into a silhouette
+                Image::new_color(settings.piece_outline_color)
+                    .rect([rect[0] + dx * outline, rect[1] + dy * outline, rect[2], rect[3]])
+                    .draw(piece_texture, &c.draw_state, c.transform, g);
             }
+            Image::new().rect(rect).draw(piece_texture, &c.draw_state, c.transform, g);
+            return;
         }
+
+        let glyph = match piece_glyph(piece) {
+            Some(glyph) => glyph,
+            None => return,
+        };
+        let font_size = (rect[3] * 0.8) as u32;
+        let pos = [rect[0] + rect[2] * 0.1, rect[1] + rect[3] * 0.82];
+
+        // The black glyphs are solid, so they also give the silhouette of white pieces
+        let kind = piece_kind(piece).unwrap();
+        let filled = piece_glyph(make_piece(Side::Black, kind)).unwrap();
+        for [dx, dy] in outline_offsets {
+            let shifted = [pos[0] + dx * outline, pos[1] + dy * outline];
+            self.draw_text(&filled.to_string(), settings.piece_outline_color, font_size, shifted, c, g);
+        }
+        if piece_side(piece) == Some(Side::White) {
+            // White glyphs are outlines, so fill them with the solid black glyph first
+            self.draw_text(&filled.to_string(), [1.0; 4], font_size, pos, c, g);
+        }
+        self.draw_text(&glyph.to_string(), [0.0, 0.0, 0.0, 1.0], font_size, pos, c, g);
     }
-}
\ No newline at end of file
+}
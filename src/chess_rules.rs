@@ -0,0 +1,1114 @@
+//! Chess rules.
+//!
+//! `dynchess-lib` moves pieces around but doesn't say which moves are legal or whether a king is
+//! in check, and can only set up the initial position, so games are played on a `Position`, which
+//! also validates moves, writes them in algebraic notation and reads and writes FEN.
+
+use dynchess_lib::ChessPiece;
+
+/// A side of the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    White,
+    Black,
+}
+
+impl Side {
+    /// Returns the other side.
+    pub fn opponent(self) -> Side {
+        match self {
+            Side::White => Side::Black,
+            Side::Black => Side::White,
+        }
+    }
+}
+
+/// A kind of piece, regardless of its color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceKind {
+    /// Returns the letter of the piece in algebraic notation.
+    pub fn letter(self) -> char {
+        match self {
+            PieceKind::Pawn => 'P',
+            PieceKind::Knight => 'N',
+            PieceKind::Bishop => 'B',
+            PieceKind::Rook => 'R',
+            PieceKind::Queen => 'Q',
+            PieceKind::King => 'K',
+        }
+    }
+
+    /// Returns the piece kind of an uppercase algebraic notation letter.
+    pub fn from_letter(letter: char) -> Option<PieceKind> {
+        match letter {
+            'P' => Some(PieceKind::Pawn),
+            'N' => Some(PieceKind::Knight),
+            'B' => Some(PieceKind::Bishop),
+            'R' => Some(PieceKind::Rook),
+            'Q' => Some(PieceKind::Queen),
+            'K' => Some(PieceKind::King),
+            _ => None,
+        }
+    }
+
+    /// Returns the material value of the piece in pawns.
+    pub fn value(self) -> u32 {
+        match self {
+            PieceKind::Pawn => 1,
+            PieceKind::Knight | PieceKind::Bishop => 3,
+            PieceKind::Rook => 5,
+            PieceKind::Queen => 9,
+            PieceKind::King => 0,
+        }
+    }
+}
+
+/// Returns the side a piece belongs to, or `None` for an empty square.
+pub fn piece_side(piece: ChessPiece) -> Option<Side> {
+    match piece {
+        ChessPiece::WPawn | ChessPiece::WKnight | ChessPiece::WBishop
+        | ChessPiece::WRook | ChessPiece::WQueen | ChessPiece::WKing => Some(Side::White),
+        ChessPiece::BPawn | ChessPiece::BKnight | ChessPiece::BBishop
+        | ChessPiece::BRook | ChessPiece::BQueen | ChessPiece::BKing => Some(Side::Black),
+        _ => None,
+    }
+}
+
+/// Returns the kind of a piece, or `None` for an empty square.
+pub fn piece_kind(piece: ChessPiece) -> Option<PieceKind> {
+    match piece {
+        ChessPiece::WPawn | ChessPiece::BPawn => Some(PieceKind::Pawn),
+        ChessPiece::WKnight | ChessPiece::BKnight => Some(PieceKind::Knight),
+        ChessPiece::WBishop | ChessPiece::BBishop => Some(PieceKind::Bishop),
+        ChessPiece::WRook | ChessPiece::BRook => Some(PieceKind::Rook),
+        ChessPiece::WQueen | ChessPiece::BQueen => Some(PieceKind::Queen),
+        ChessPiece::WKing | ChessPiece::BKing => Some(PieceKind::King),
+        _ => None,
+    }
+}
+
+/// Returns the piece of the given side and kind.
+pub fn make_piece(side: Side, kind: PieceKind) -> ChessPiece {
+    match (side, kind) {
+        (Side::White, PieceKind::Pawn) => ChessPiece::WPawn,
+        (Side::White, PieceKind::Knight) => ChessPiece::WKnight,
+        (Side::White, PieceKind::Bishop) => ChessPiece::WBishop,
+        (Side::White, PieceKind::Rook) => ChessPiece::WRook,
+        (Side::White, PieceKind::Queen) => ChessPiece::WQueen,
+        (Side::White, PieceKind::King) => ChessPiece::WKing,
+        (Side::Black, PieceKind::Pawn) => ChessPiece::BPawn,
+        (Side::Black, PieceKind::Knight) => ChessPiece::BKnight,
+        (Side::Black, PieceKind::Bishop) => ChessPiece::BBishop,
+        (Side::Black, PieceKind::Rook) => ChessPiece::BRook,
+        (Side::Black, PieceKind::Queen) => ChessPiece::BQueen,
+        (Side::Black, PieceKind::King) => ChessPiece::BKing,
+    }
+}
+
+/// Returns the file (0 = a) of a square index.
+pub fn file_of(square: u8) -> u8 {
+    square % 8
+}
+
+/// Returns the rank (0 = 1st rank) of a square index.
+pub fn rank_of(square: u8) -> u8 {
+    square / 8
+}
+
+/// Returns the name of a square index, e.g. `e4`.
+pub fn square_name(square: u8) -> String {
+    format!("{}{}", (b'a' + file_of(square)) as char, rank_of(square) + 1)
+}
+
+/// Parses a square name such as `e4` into a square index.
+pub fn parse_square(name: &str) -> Option<u8> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 {
+        return None;
+    }
+    let (file, rank) = (bytes[0], bytes[1]);
+    if !(b'a'..=b'h').contains(&file) || !(b'1'..=b'8').contains(&rank) {
+        return None;
+    }
+    Some((file - b'a') + (rank - b'1') * 8)
+}
+
+/// Returns the square `files` and `ranks` away from `square`, if it is on the board.
+fn offset_square(square: u8, files: i8, ranks: i8) -> Option<u8> {
+    let file = file_of(square) as i8 + files;
+    let rank = rank_of(square) as i8 + ranks;
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((file + rank * 8) as u8)
+    } else {
+        None
+    }
+}
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2),
+];
+const KING_OFFSETS: [(i8, i8); 8] = [
+    (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1),
+];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const PROMOTION_KINDS: [PieceKind; 4] =
+    [PieceKind::Queen, PieceKind::Rook, PieceKind::Bishop, PieceKind::Knight];
+
+/// A move from one square index to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChessMove {
+    pub from: u8,
+    pub to: u8,
+    /// Piece a pawn promotes to, a queen if left out.
+    pub promotion: Option<PieceKind>,
+}
+
+impl ChessMove {
+    /// Creates a move without promotion.
+    pub fn new(from: u8, to: u8) -> ChessMove {
+        ChessMove { from, to, promotion: None }
+    }
+
+    /// Parses a move in UCI notation, e.g. `e2e4` or `e7e8q`.
+    pub fn from_uci(uci: &str) -> Option<ChessMove> {
+        if !uci.is_ascii() || (uci.len() != 4 && uci.len() != 5) {
+            return None;
+        }
+        let promotion = match uci[4..].chars().next() {
+            Some(letter) => Some(PieceKind::from_letter(letter.to_ascii_uppercase())?),
+            None => None,
+        };
+        Some(ChessMove {
+            from: parse_square(&uci[0..2])?,
+            to: parse_square(&uci[2..4])?,
+            promotion,
+        })
+    }
+
+    /// Returns the move in UCI notation, e.g. `e2e4` or `e7e8q`.
+    pub fn uci(&self) -> String {
+        let mut uci = square_name(self.from) + &square_name(self.to);
+        if let Some(kind) = self.promotion {
+            uci.push(kind.letter().to_ascii_lowercase());
+        }
+        uci
+    }
+}
+
+/// Which castling moves are still allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+}
+
+impl CastlingRights {
+    /// Returns rights with every castling move allowed.
+    pub fn all() -> CastlingRights {
+        CastlingRights {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+        }
+    }
+
+    /// Returns rights with no castling move allowed.
+    pub fn none() -> CastlingRights {
+        CastlingRights {
+            white_king_side: false,
+            white_queen_side: false,
+            black_king_side: false,
+            black_queen_side: false,
+        }
+    }
+
+    /// Returns whether `side` may castle on the king side (`true`) or queen side (`false`).
+    fn allows(&self, side: Side, king_side: bool) -> bool {
+        match (side, king_side) {
+            (Side::White, true) => self.white_king_side,
+            (Side::White, false) => self.white_queen_side,
+            (Side::Black, true) => self.black_king_side,
+            (Side::Black, false) => self.black_queen_side,
+        }
+    }
+
+    /// Removes the right to castle with the rook that starts on `square`.
+    fn remove_rook(&mut self, square: u8) {
+        match square {
+            0 => self.white_queen_side = false,
+            7 => self.white_king_side = false,
+            56 => self.black_queen_side = false,
+            63 => self.black_king_side = false,
+            _ => {}
+        }
+    }
+}
+
+/// How a game has ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    Checkmate { winner: Side },
+    Stalemate,
+    InsufficientMaterial,
+    FiftyMoveRule,
+}
+
+/// A chess position with everything needed to generate legal moves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// Pieces indexed by square, a1 = 0 and h8 = 63, like `ChessBoard::get_board`.
+    pub board: [ChessPiece; 64],
+    pub side_to_move: Side,
+    pub castling: CastlingRights,
+    /// Square a pawn may capture onto en passant.
+    pub en_passant: Option<u8>,
+    /// Half moves since the last capture or pawn move.
+    pub halfmove_clock: u32,
+    /// Full move number, starting at 1 and incremented after Black moves.
+    pub fullmove_number: u32,
+}
+
+impl Position {
+    /// Creates the standard starting position, matching `ChessBoard::init_position`.
+    pub fn initial() -> Position {
+        let back_rank = [
+            PieceKind::Rook, PieceKind::Knight, PieceKind::Bishop, PieceKind::Queen,
+            PieceKind::King, PieceKind::Bishop, PieceKind::Knight, PieceKind::Rook,
+        ];
+        let mut board = [ChessPiece::Empty; 64];
+        for (file, kind) in back_rank.iter().enumerate() {
+            board[file] = make_piece(Side::White, *kind);
+            board[8 + file] = ChessPiece::WPawn;
+            board[48 + file] = ChessPiece::BPawn;
+            board[56 + file] = make_piece(Side::Black, *kind);
+        }
+
+        Position {
+            board,
+            side_to_move: Side::White,
+            castling: CastlingRights::all(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    /// Creates a position without any pieces, with White to move.
+    pub fn empty() -> Position {
+        Position {
+            board: [ChessPiece::Empty; 64],
+            side_to_move: Side::White,
+            castling: CastlingRights::none(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    /// Reads a position in Forsyth-Edwards Notation.
+    ///
+    /// The halfmove clock and fullmove number may be left out, as some tools do. The position
+    /// isn't checked to be playable, see `validate`.
+    pub fn from_fen(fen: &str) -> Result<Position, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(format!("Expected 4 or 6 fields in FEN, found {}", fields.len()));
+        }
+        let mut position = Position::empty();
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("Expected 8 ranks in FEN, found {}", ranks.len()));
+        }
+        // Ranks are listed from the 8th down
+        for (row, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - row as u8;
+            let mut file = 0u8;
+            for c in rank_text.chars() {
+                if let Some(empty) = c.to_digit(10).filter(|digit| (1..=8).contains(digit)) {
+                    file += empty as u8;
+                } else {
+                    let kind = PieceKind::from_letter(c.to_ascii_uppercase())
+                        .ok_or_else(|| format!("Unknown piece '{}' in FEN", c))?;
+                    let side = if c.is_ascii_uppercase() { Side::White } else { Side::Black };
+                    if file < 8 {
+                        position.board[(file + rank * 8) as usize] = make_piece(side, kind);
+                    }
+                    file += 1;
+                }
+                if file > 8 {
+                    break;
+                }
+            }
+            if file != 8 {
+                return Err(format!("Rank {} in FEN doesn't have 8 squares", rank + 1));
+            }
+        }
+
+        position.side_to_move = match fields[1] {
+            "w" => Side::White,
+            "b" => Side::Black,
+            other => return Err(format!("Unknown side to move '{}' in FEN", other)),
+        };
+
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                match c {
+                    'K' => position.castling.white_king_side = true,
+                    'Q' => position.castling.white_queen_side = true,
+                    'k' => position.castling.black_king_side = true,
+                    'q' => position.castling.black_queen_side = true,
+                    _ => return Err(format!("Unknown castling right '{}' in FEN", c)),
+                }
+            }
+        }
+
+        position.en_passant = match fields[3] {
+            "-" => None,
+            name => Some(parse_square(name).ok_or_else(|| format!("Unknown en passant square '{}' in FEN", name))?),
+        };
+
+        if fields.len() == 6 {
+            position.halfmove_clock = fields[4]
+                .parse()
+                .map_err(|_| format!("Invalid halfmove clock '{}' in FEN", fields[4]))?;
+            position.fullmove_number = fields[5]
+                .parse()
+                .ok()
+                .filter(|number| *number > 0)
+                .ok_or_else(|| format!("Invalid fullmove number '{}' in FEN", fields[5]))?;
+        }
+
+        Ok(position)
+    }
+
+    /// Writes the position in Forsyth-Edwards Notation.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8u8).rev() {
+            let mut empty = 0;
+            for file in 0..8u8 {
+                let piece = self.board[(file + rank * 8) as usize];
+                match (piece_side(piece), piece_kind(piece)) {
+                    (Some(side), Some(kind)) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let letter = kind.letter();
+                        fen.push(if side == Side::White { letter } else { letter.to_ascii_lowercase() });
+                    }
+                    _ => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if self.side_to_move == Side::White { " w " } else { " b " });
+
+        let castling: String = [
+            (self.castling.white_king_side, 'K'),
+            (self.castling.white_queen_side, 'Q'),
+            (self.castling.black_king_side, 'k'),
+            (self.castling.black_queen_side, 'q'),
+        ]
+        .iter()
+        .filter(|(allowed, _)| *allowed)
+        .map(|(_, letter)| *letter)
+        .collect();
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+        let en_passant = self.en_passant.map_or(String::from("-"), square_name);
+        fen.push_str(&format!(" {} {} {}", en_passant, self.halfmove_clock, self.fullmove_number));
+        fen
+    }
+
+    /// Returns the squares that could be the en passant square, where a pawn of the side not to
+    /// move could have just passed over with a double step.
+    pub fn en_passant_candidates(&self) -> Vec<u8> {
+        // The passed square is on the 6th rank when White is to move, and the 3rd for Black
+        let (rank, pawn, forward) = match self.side_to_move {
+            Side::White => (5u8, ChessPiece::BPawn, -8i8),
+            Side::Black => (2u8, ChessPiece::WPawn, 8i8),
+        };
+        (0..8u8)
+            .map(|file| file + rank * 8)
+            .filter(|square| {
+                let pawn_square = (*square as i8 + forward) as usize;
+                let start_square = (*square as i8 - forward) as usize;
+                self.board[*square as usize] == ChessPiece::Empty
+                    && self.board[start_square] == ChessPiece::Empty
+                    && self.board[pawn_square] == pawn
+            })
+            .collect()
+    }
+
+    /// Checks that the position could come up in a game, e.g. one set up in the board editor or
+    /// read from FEN, and says what is wrong if it couldn't.
+    pub fn validate(&self) -> Result<(), String> {
+        for side in [Side::White, Side::Black] {
+            let name = if side == Side::White { "White" } else { "Black" };
+            let pieces: Vec<PieceKind> = self.board
+                .iter()
+                .filter(|piece| piece_side(**piece) == Some(side))
+                .filter_map(|piece| piece_kind(*piece))
+                .collect();
+            let count = |kind: PieceKind| pieces.iter().filter(|piece| **piece == kind).count();
+
+            if count(PieceKind::King) != 1 {
+                return Err(format!("{} needs exactly one king", name));
+            }
+            if count(PieceKind::Pawn) > 8 {
+                return Err(format!("{} has more than 8 pawns", name));
+            }
+            if pieces.len() > 16 {
+                return Err(format!("{} has more than 16 pieces", name));
+            }
+        }
+
+        let mut back_ranks = (0..8).chain(56..64);
+        if back_ranks.any(|square| piece_kind(self.board[square]) == Some(PieceKind::Pawn)) {
+            return Err(String::from("Pawns can't be on the first or last rank"));
+        }
+
+        let waiting = self.side_to_move.opponent();
+        if self.king_square(waiting).is_some_and(|king| self.is_attacked(king, self.side_to_move)) {
+            return Err(String::from("The side not to move is in check"));
+        }
+
+        // Castling needs the king and the rook on their starting squares
+        let castling = [
+            (self.castling.white_king_side, ChessPiece::WKing, 4, ChessPiece::WRook, 7),
+            (self.castling.white_queen_side, ChessPiece::WKing, 4, ChessPiece::WRook, 0),
+            (self.castling.black_king_side, ChessPiece::BKing, 60, ChessPiece::BRook, 63),
+            (self.castling.black_queen_side, ChessPiece::BKing, 60, ChessPiece::BRook, 56),
+        ];
+        for (allowed, king, king_square, rook, rook_square) in castling {
+            if allowed && (self.board[king_square] != king || self.board[rook_square] != rook) {
+                return Err(format!(
+                    "Castling with the rook on {} needs the king and rook on their starting squares",
+                    square_name(rook_square as u8)
+                ));
+            }
+        }
+
+        if let Some(square) = self.en_passant {
+            if !self.en_passant_candidates().contains(&square) {
+                return Err(format!("No pawn can have just passed over {}", square_name(square)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the square of the king of `side`, if there is one.
+    pub fn king_square(&self, side: Side) -> Option<u8> {
+        let king = make_piece(side, PieceKind::King);
+        (0..64).find(|square| self.board[*square as usize] == king)
+    }
+
+    /// Returns whether any piece of `by` attacks `square`.
+    pub fn is_attacked(&self, square: u8, by: Side) -> bool {
+        let is = |square: Option<u8>, kinds: &[PieceKind]| {
+            square.is_some_and(|square| {
+                let piece = self.board[square as usize];
+                piece_side(piece) == Some(by)
+                    && kinds.contains(&piece_kind(piece).unwrap())
+            })
+        };
+
+        // A pawn of `by` attacks diagonally towards the opponent
+        let pawn_rank = if by == Side::White { -1 } else { 1 };
+        if is(offset_square(square, -1, pawn_rank), &[PieceKind::Pawn])
+            || is(offset_square(square, 1, pawn_rank), &[PieceKind::Pawn])
+        {
+            return true;
+        }
+        if KNIGHT_OFFSETS.iter().any(|(f, r)| is(offset_square(square, *f, *r), &[PieceKind::Knight])) {
+            return true;
+        }
+        if KING_OFFSETS.iter().any(|(f, r)| is(offset_square(square, *f, *r), &[PieceKind::King])) {
+            return true;
+        }
+
+        let sliders = [
+            (ROOK_DIRECTIONS, [PieceKind::Rook, PieceKind::Queen]),
+            (BISHOP_DIRECTIONS, [PieceKind::Bishop, PieceKind::Queen]),
+        ];
+        for (directions, kinds) in sliders {
+            for (f, r) in directions {
+                let mut current = offset_square(square, f, r);
+                while let Some(target) = current {
+                    if self.board[target as usize] != ChessPiece::Empty {
+                        if is(Some(target), &kinds) {
+                            return true;
+                        }
+                        break;
+                    }
+                    current = offset_square(target, f, r);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Returns whether the side to move is in check.
+    pub fn in_check(&self) -> bool {
+        self.king_square(self.side_to_move)
+            .is_some_and(|king| self.is_attacked(king, self.side_to_move.opponent()))
+    }
+
+    /// Returns how the game has ended in this position, or `None` if it goes on.
+    pub fn result(&self) -> Option<GameResult> {
+        if self.legal_moves().is_empty() {
+            return Some(if self.in_check() {
+                GameResult::Checkmate { winner: self.side_to_move.opponent() }
+            } else {
+                GameResult::Stalemate
+            });
+        }
+        if self.halfmove_clock >= 100 {
+            return Some(GameResult::FiftyMoveRule);
+        }
+
+        // Only kings, plus at most one knight or bishop, can't checkmate
+        let mut minor_pieces = 0;
+        for piece in self.board {
+            match piece_kind(piece) {
+                None | Some(PieceKind::King) => {}
+                Some(PieceKind::Knight) | Some(PieceKind::Bishop) => minor_pieces += 1,
+                Some(_) => return None,
+            }
+        }
+        if minor_pieces <= 1 {
+            return Some(GameResult::InsufficientMaterial);
+        }
+
+        None
+    }
+
+    /// Returns the moves of the side to move, ignoring whether they leave the king in check.
+    fn pseudo_legal_moves(&self) -> Vec<ChessMove> {
+        let side = self.side_to_move;
+        let mut moves = Vec::new();
+
+        for from in 0..64u8 {
+            let piece = self.board[from as usize];
+            if piece_side(piece) != Some(side) {
+                continue;
+            }
+            let can_land = |to: u8| piece_side(self.board[to as usize]) != Some(side);
+
+            match piece_kind(piece).unwrap() {
+                PieceKind::Pawn => {
+                    let (forward, start_rank, last_rank) = match side {
+                        Side::White => (1, 1, 7),
+                        Side::Black => (-1, 6, 0),
+                    };
+                    let mut targets = Vec::new();
+                    if let Some(one) = offset_square(from, 0, forward) {
+                        if self.board[one as usize] == ChessPiece::Empty {
+                            targets.push(one);
+                            if rank_of(from) == start_rank {
+                                let two = offset_square(from, 0, forward * 2).unwrap();
+                                if self.board[two as usize] == ChessPiece::Empty {
+                                    targets.push(two);
+                                }
+                            }
+                        }
+                    }
+                    for files in [-1, 1] {
+                        if let Some(to) = offset_square(from, files, forward) {
+                            let target_side = piece_side(self.board[to as usize]);
+                            if target_side == Some(side.opponent()) || self.en_passant == Some(to) {
+                                targets.push(to);
+                            }
+                        }
+                    }
+                    for to in targets {
+                        if rank_of(to) == last_rank {
+                            for kind in PROMOTION_KINDS {
+                                moves.push(ChessMove { from, to, promotion: Some(kind) });
+                            }
+                        } else {
+                            moves.push(ChessMove::new(from, to));
+                        }
+                    }
+                }
+                PieceKind::Knight | PieceKind::King => {
+                    let offsets = if piece_kind(piece) == Some(PieceKind::Knight) {
+                        KNIGHT_OFFSETS
+                    } else {
+                        KING_OFFSETS
+                    };
+                    for (f, r) in offsets {
+                        if let Some(to) = offset_square(from, f, r) {
+                            if can_land(to) {
+                                moves.push(ChessMove::new(from, to));
+                            }
+                        }
+                    }
+                }
+                kind => {
+                    let directions: &[(i8, i8)] = match kind {
+                        PieceKind::Rook => &ROOK_DIRECTIONS,
+                        PieceKind::Bishop => &BISHOP_DIRECTIONS,
+                        // A queen slides in every direction a king steps in
+                        _ => &KING_OFFSETS,
+                    };
+                    for (f, r) in directions {
+                        let mut current = offset_square(from, *f, *r);
+                        while let Some(to) = current {
+                            if !can_land(to) {
+                                break;
+                            }
+                            moves.push(ChessMove::new(from, to));
+                            if self.board[to as usize] != ChessPiece::Empty {
+                                break;
+                            }
+                            current = offset_square(to, *f, *r);
+                        }
+                    }
+                }
+            }
+        }
+
+        moves.extend(self.castling_moves());
+        moves
+    }
+
+    /// Returns the castling moves of the side to move, as king moves of two squares.
+    fn castling_moves(&self) -> Vec<ChessMove> {
+        let side = self.side_to_move;
+        let base = if side == Side::White { 0 } else { 56 };
+        let mut moves = Vec::new();
+
+        if self.board[base as usize + 4] != make_piece(side, PieceKind::King) || self.in_check() {
+            return moves;
+        }
+
+        // (king side, rook file, files that must be empty, files the king passes)
+        let options: [(bool, u8, &[u8], [u8; 2]); 2] = [
+            (true, 7, &[5, 6], [5, 6]),
+            (false, 0, &[1, 2, 3], [3, 2]),
+        ];
+        for (king_side, rook_file, empty, passed) in options {
+            if self.castling.allows(side, king_side)
+                && self.board[(base + rook_file) as usize] == make_piece(side, PieceKind::Rook)
+                && empty.iter().all(|file| self.board[(base + file) as usize] == ChessPiece::Empty)
+                && passed.iter().all(|file| !self.is_attacked(base + file, side.opponent()))
+            {
+                moves.push(ChessMove::new(base + 4, base + passed[1]));
+            }
+        }
+
+        moves
+    }
+
+    /// Returns every legal move of the side to move.
+    pub fn legal_moves(&self) -> Vec<ChessMove> {
+        let side = self.side_to_move;
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|chess_move| {
+                let mut after = self.clone();
+                after.make_move(*chess_move);
+                !after.king_square(side)
+                    .is_some_and(|king| after.is_attacked(king, side.opponent()))
+            })
+            .collect()
+    }
+
+    /// Fills in a queen for promotions that don't name a piece, and drops the promotion of moves
+    /// that don't promote.
+    pub fn normalize(&self, chess_move: ChessMove) -> ChessMove {
+        let piece = self.board[chess_move.from as usize];
+        let promotes = piece_kind(piece) == Some(PieceKind::Pawn)
+            && (rank_of(chess_move.to) == 0 || rank_of(chess_move.to) == 7);
+        ChessMove {
+            promotion: if promotes {
+                Some(chess_move.promotion.unwrap_or(PieceKind::Queen))
+            } else {
+                None
+            },
+            ..chess_move
+        }
+    }
+
+    /// Returns whether `chess_move` is legal for the side to move.
+    pub fn is_legal(&self, chess_move: ChessMove) -> bool {
+        self.legal_moves().contains(&self.normalize(chess_move))
+    }
+
+    /// Returns the piece `chess_move` captures, if any, including pawns taken en passant.
+    pub fn captured_piece(&self, chess_move: ChessMove) -> Option<ChessPiece> {
+        let (from, to) = (chess_move.from, chess_move.to);
+        let target = self.board[to as usize];
+        if target != ChessPiece::Empty {
+            return Some(target);
+        }
+        let pawn = piece_kind(self.board[from as usize]) == Some(PieceKind::Pawn);
+        if pawn && Some(to) == self.en_passant && file_of(from) != file_of(to) {
+            return Some(self.board[(file_of(to) + rank_of(from) * 8) as usize]);
+        }
+        None
+    }
+
+    /// Plays `chess_move` without checking that it is legal.
+    pub fn make_move(&mut self, chess_move: ChessMove) {
+        let chess_move = self.normalize(chess_move);
+        let (from, to) = (chess_move.from, chess_move.to);
+        let piece = self.board[from as usize];
+        let side = match piece_side(piece) {
+            Some(side) => side,
+            None => return,
+        };
+        let kind = piece_kind(piece).unwrap();
+        let mut is_capture = self.board[to as usize] != ChessPiece::Empty;
+
+        if kind == PieceKind::Pawn && Some(to) == self.en_passant && file_of(from) != file_of(to) {
+            // The captured pawn is beside the moving pawn, not on the target square
+            let captured = file_of(to) + rank_of(from) * 8;
+            self.board[captured as usize] = ChessPiece::Empty;
+            is_capture = true;
+        }
+
+        if kind == PieceKind::King && file_of(from).abs_diff(file_of(to)) == 2 {
+            let base = rank_of(from) * 8;
+            let (rook_from, rook_to) = if file_of(to) == 6 { (base + 7, base + 5) } else { (base, base + 3) };
+            self.board[rook_to as usize] = self.board[rook_from as usize];
+            self.board[rook_from as usize] = ChessPiece::Empty;
+        }
+
+        self.board[to as usize] = match chess_move.promotion {
+            Some(promotion) => make_piece(side, promotion),
+            None => piece,
+        };
+        self.board[from as usize] = ChessPiece::Empty;
+
+        if kind == PieceKind::King {
+            match side {
+                Side::White => {
+                    self.castling.white_king_side = false;
+                    self.castling.white_queen_side = false;
+                }
+                Side::Black => {
+                    self.castling.black_king_side = false;
+                    self.castling.black_queen_side = false;
+                }
+            }
+        }
+        self.castling.remove_rook(from);
+        self.castling.remove_rook(to);
+
+        self.en_passant = if kind == PieceKind::Pawn && rank_of(from).abs_diff(rank_of(to)) == 2 {
+            Some((from + to) / 2)
+        } else {
+            None
+        };
+        self.halfmove_clock = if kind == PieceKind::Pawn || is_capture { 0 } else { self.halfmove_clock + 1 };
+        if side == Side::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = side.opponent();
+    }
+
+    /// Returns `chess_move` in Standard Algebraic Notation, e.g. `Nbxd7+`.
+    pub fn san(&self, chess_move: ChessMove) -> String {
+        let chess_move = self.normalize(chess_move);
+        let (from, to) = (chess_move.from, chess_move.to);
+        let piece = self.board[from as usize];
+        let kind = match piece_kind(piece) {
+            Some(kind) => kind,
+            None => return chess_move.uci(),
+        };
+
+        let mut san = String::new();
+        if kind == PieceKind::King && file_of(from).abs_diff(file_of(to)) == 2 {
+            san.push_str(if file_of(to) == 6 { "O-O" } else { "O-O-O" });
+        } else if kind == PieceKind::Pawn {
+            if file_of(from) != file_of(to) {
+                san.push((b'a' + file_of(from)) as char);
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+            if let Some(promotion) = chess_move.promotion {
+                san.push('=');
+                san.push(promotion.letter());
+            }
+        } else {
+            san.push(kind.letter());
+
+            // Other pieces of the same kind that could also move to the target square
+            let rivals: Vec<u8> = self.legal_moves()
+                .iter()
+                .filter(|other| other.to == to && other.from != from && self.board[other.from as usize] == piece)
+                .map(|other| other.from)
+                .collect();
+            if !rivals.is_empty() {
+                if rivals.iter().all(|rival| file_of(*rival) != file_of(from)) {
+                    san.push((b'a' + file_of(from)) as char);
+                } else if rivals.iter().all(|rival| rank_of(*rival) != rank_of(from)) {
+                    san.push((b'1' + rank_of(from)) as char);
+                } else {
+                    san.push_str(&square_name(from));
+                }
+            }
+
+            if self.board[to as usize] != ChessPiece::Empty {
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+        }
+
+        let mut after = self.clone();
+        after.make_move(chess_move);
+        if after.in_check() {
+            san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+
+        san
+    }
+
+    /// Finds the legal move written as `text`, in SAN such as `Nf3` or `exd5`, or in UCI notation
+    /// such as `g1f3`.
+    ///
+    /// Check, capture and promotion marks are optional in SAN, and castling may be written with
+    /// zeros.
+    pub fn parse_move(&self, text: &str) -> Option<ChessMove> {
+        let text = text.trim();
+        if let Some(chess_move) = ChessMove::from_uci(text) {
+            if self.is_legal(chess_move) {
+                return Some(self.normalize(chess_move));
+            }
+        }
+
+        // SAN is unique among the legal moves even without the optional marks
+        let bare = |san: &str| -> String {
+            san.chars()
+                .filter(|c| !"+#!?x=-".contains(*c))
+                .map(|c| if c == '0' { 'O' } else { c })
+                .collect()
+        };
+        let wanted = bare(text);
+        self.legal_moves()
+            .into_iter()
+            .find(|chess_move| bare(&self.san(*chess_move)) == wanted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    /// Counts the positions reached after `depth` legal moves.
+    fn perft(position: &Position, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        position
+            .legal_moves()
+            .into_iter()
+            .map(|chess_move| {
+                let mut after = position.clone();
+                after.make_move(chess_move);
+                perft(&after, depth - 1)
+            })
+            .sum()
+    }
+
+    /// Returns the move written in UCI notation, checking that it is legal.
+    fn legal(position: &Position, uci: &str) -> ChessMove {
+        let chess_move = ChessMove::from_uci(uci).unwrap();
+        assert!(position.is_legal(chess_move), "{} isn't legal in {}", uci, position.to_fen());
+        position.normalize(chess_move)
+    }
+
+    #[test]
+    fn perft_from_the_start_position() {
+        let position = Position::initial();
+        assert_eq!(perft(&position, 1), 20);
+        assert_eq!(perft(&position, 2), 400);
+        assert_eq!(perft(&position, 3), 8902);
+    }
+
+    #[test]
+    fn perft_from_kiwipete() {
+        let position = Position::from_fen(KIWIPETE).unwrap();
+        assert_eq!(perft(&position, 1), 48);
+        assert_eq!(perft(&position, 2), 2039);
+        assert_eq!(perft(&position, 3), 97862);
+    }
+
+    #[test]
+    fn king_cant_castle_through_check() {
+        // The bishop on c4 attacks f1
+        let position = Position::from_fen("4k3/8/8/8/2b5/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert!(!position.is_legal(ChessMove::new(4, 6)));
+        assert!(position.is_legal(ChessMove::new(4, 2)));
+
+        // Nor out of it
+        let position = Position::from_fen("4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1").unwrap();
+        assert!(!position.is_legal(ChessMove::new(4, 6)));
+        assert!(!position.is_legal(ChessMove::new(4, 2)));
+    }
+
+    #[test]
+    fn castling_moves_the_rook_and_loses_the_rights() {
+        let mut position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        position.make_move(legal(&position, "e1g1"));
+        assert_eq!(position.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+        assert_eq!(position.san(ChessMove::new(60, 58)), "O-O-O");
+    }
+
+    #[test]
+    fn captured_piece_is_the_one_on_the_target_square() {
+        let position = Position::from_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(position.captured_piece(legal(&position, "e4d5")), Some(ChessPiece::BQueen));
+        assert_eq!(position.captured_piece(legal(&position, "e4e5")), None);
+    }
+
+    #[test]
+    fn en_passant_captures_the_passed_pawn() {
+        let mut position = Position::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+        let capture = legal(&position, "e5d6");
+        assert_eq!(position.san(capture), "exd6");
+        assert_eq!(position.captured_piece(capture), Some(ChessPiece::BPawn));
+        position.make_move(capture);
+        assert_eq!(position.to_fen(), "4k3/8/3P4/8/8/8/8/4K3 b - - 0 2");
+    }
+
+    #[test]
+    fn en_passant_is_illegal_when_it_exposes_the_king() {
+        // Taking removes both pawns from the fifth rank, opening it for the rook
+        let position = Position::from_fen("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 2").unwrap();
+        assert!(!position.is_legal(ChessMove::new(36, 43)));
+        assert!(position.is_legal(ChessMove::new(36, 44)));
+    }
+
+    #[test]
+    fn promotion_defaults_to_a_queen() {
+        let position = Position::from_fen("8/P6k/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let promotions = position.legal_moves().into_iter().filter(|chess_move| chess_move.from == 48).count();
+        assert_eq!(promotions, 4);
+
+        assert_eq!(position.normalize(ChessMove::new(48, 56)).promotion, Some(PieceKind::Queen));
+        let mut queen = position.clone();
+        queen.make_move(ChessMove::new(48, 56));
+        assert!(queen.board[56] == ChessPiece::WQueen);
+
+        let knight = legal(&position, "a7a8n");
+        assert_eq!(position.san(knight), "a8=N");
+        let mut after = position.clone();
+        after.make_move(knight);
+        assert!(after.board[56] == ChessPiece::WKnight);
+        assert_eq!(position.parse_move("a8=N"), Some(knight));
+    }
+
+    #[test]
+    fn san_tells_apart_pieces_that_can_reach_the_same_square() {
+        // Knights on b1 and f1 can both go to d2, rooks on a1 and a5 can both go to a3
+        let position = Position::from_fen("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1").unwrap();
+        assert_eq!(position.san(ChessMove::new(1, 11)), "Nbd2");
+        assert_eq!(position.san(ChessMove::new(0, 16)), "R1a3");
+        assert_eq!(position.san(ChessMove::new(32, 16)), "R5a3");
+        // Queens on a1, c1 and a3 can all go to b2, so the square is needed
+        let position = Position::from_fen("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1").unwrap();
+        assert_eq!(position.san(ChessMove::new(0, 9)), "Qa1b2");
+        assert_eq!(position.parse_move("Qa1b2"), Some(ChessMove::new(0, 9)));
+    }
+
+    #[test]
+    fn san_marks_check_and_mate() {
+        let position = Position::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        assert_eq!(position.san(ChessMove::new(59, 31)), "Qh4#");
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_eq!(position.san(ChessMove::new(0, 56)), "Ra8+");
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            KIWIPETE,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 b - - 99 120",
+        ] {
+            assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+        }
+        assert_eq!(Position::from_fen(&Position::initial().to_fen()).unwrap(), Position::initial());
+        // The clocks may be left out
+        assert_eq!(Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap().to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn invalid_fen_is_an_error() {
+        for fen in [
+            "",
+            "4k3/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4X3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w X - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - z9 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+        ] {
+            assert!(Position::from_fen(fen).is_err(), "{} was read", fen);
+        }
+    }
+
+    #[test]
+    fn parse_move_reads_san_and_uci() {
+        let position = Position::initial();
+        assert_eq!(position.parse_move("Nf3"), Some(ChessMove::new(6, 21)));
+        assert_eq!(position.parse_move("g1f3"), Some(ChessMove::new(6, 21)));
+        assert_eq!(position.parse_move(" e4 "), Some(ChessMove::new(12, 28)));
+        let castling = Position::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(castling.parse_move("0-0"), Some(ChessMove::new(4, 6)));
+        assert_eq!(castling.parse_move("O-O+"), Some(ChessMove::new(4, 6)));
+    }
+
+    #[test]
+    fn parse_move_rejects_illegal_and_unknown_moves() {
+        let position = Position::initial();
+        for text in ["", "e5", "Ke2", "e2e5", "Nd2", "O-O", "z9", "Qxf7#", "e7e8q", "hello"] {
+            assert_eq!(position.parse_move(text), None, "{} was read", text);
+        }
+        // Ambiguous moves need the piece told apart
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/RN2KN2 w - - 0 1").unwrap();
+        assert_eq!(position.parse_move("Nd2"), None);
+        assert_eq!(position.parse_move("Nfd2"), Some(ChessMove::new(5, 11)));
+    }
+
+    #[test]
+    fn uci_round_trips() {
+        for text in ["e2e4", "a7a8q", "h2h1n"] {
+            assert_eq!(ChessMove::from_uci(text).unwrap().uci(), text);
+        }
+        for text in ["e2e", "e2e4x", "i2e4", "e2e4qq", "é2e4"] {
+            assert_eq!(ChessMove::from_uci(text), None, "{} was read", text);
+        }
+    }
+}
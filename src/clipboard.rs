@@ -0,0 +1,120 @@
+//! Copying and pasting text, through the clipboard tools of the platform or in memory.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Text clipboard.
+pub trait Clipboard {
+    /// Returns the text on the clipboard.
+    fn get_text(&mut self) -> io::Result<String>;
+    /// Puts `text` on the clipboard.
+    fn set_text(&mut self, text: &str) -> io::Result<()>;
+}
+
+/// The clipboard of the desktop, used with `pbcopy` on macOS, `clip` on Windows and
+/// `wl-clipboard`, `xclip` or `xsel` elsewhere, whichever is installed.
+pub struct SystemClipboard;
+
+impl SystemClipboard {
+    /// Returns the commands that copy stdin to the clipboard, in the order they are tried.
+    fn copy_commands() -> Vec<&'static [&'static str]> {
+        if cfg!(target_os = "macos") {
+            vec![&["pbcopy"]]
+        } else if cfg!(windows) {
+            vec![&["clip"]]
+        } else {
+            let mut commands: Vec<&'static [&'static str]> = Vec::new();
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                commands.push(&["wl-copy"]);
+            }
+            commands.push(&["xclip", "-selection", "clipboard"]);
+            commands.push(&["xsel", "--clipboard", "--input"]);
+            commands
+        }
+    }
+
+    /// Returns the commands that print the clipboard, in the order they are tried.
+    fn paste_commands() -> Vec<&'static [&'static str]> {
+        if cfg!(target_os = "macos") {
+            vec![&["pbpaste"]]
+        } else if cfg!(windows) {
+            vec![&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]]
+        } else {
+            let mut commands: Vec<&'static [&'static str]> = Vec::new();
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                commands.push(&["wl-paste", "--no-newline"]);
+            }
+            commands.push(&["xclip", "-selection", "clipboard", "-out"]);
+            commands.push(&["xsel", "--clipboard", "--output"]);
+            commands
+        }
+    }
+}
+
+/// Returns the error for when none of the clipboard tools is installed.
+fn no_clipboard_tool() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no clipboard tool found, install xclip, xsel or wl-clipboard")
+}
+
+/// Returns an error for a clipboard tool that exited unsuccessfully.
+fn tool_failed(program: &str) -> io::Error {
+    io::Error::other(format!("{} failed", program))
+}
+
+impl Clipboard for SystemClipboard {
+    fn get_text(&mut self) -> io::Result<String> {
+        for command in Self::paste_commands() {
+            let output = match Command::new(command[0]).args(&command[1..]).stderr(Stdio::null()).output() {
+                // Not installed, try the next one
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                result => result?,
+            };
+            if !output.status.success() {
+                return Err(tool_failed(command[0]));
+            }
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        Err(no_clipboard_tool())
+    }
+
+    fn set_text(&mut self, text: &str) -> io::Result<()> {
+        for command in Self::copy_commands() {
+            let spawned = Command::new(command[0])
+                .args(&command[1..])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            let mut child = match spawned {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                result => result?,
+            };
+            // Closing stdin tells the tool the text is complete
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(text.as_bytes())?;
+            }
+            if !child.wait()?.success() {
+                return Err(tool_failed(command[0]));
+            }
+            return Ok(());
+        }
+        Err(no_clipboard_tool())
+    }
+}
+
+/// Clipboard that only keeps the text in memory, e.g. for tests or machines without a desktop.
+#[derive(Default)]
+pub struct MemoryClipboard {
+    pub text: String,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_text(&mut self) -> io::Result<String> {
+        Ok(self.text.clone())
+    }
+
+    fn set_text(&mut self, text: &str) -> io::Result<()> {
+        self.text = String::from(text);
+        Ok(())
+    }
+}
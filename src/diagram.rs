@@ -0,0 +1,197 @@
+//! Position diagrams rendered to SVG or PNG without OpenGL.
+//!
+//! The layout comes from `ChessGraphicsSettings`, the same as in `ChessGraphics::draw`, so a
+//! diagram looks like the board on screen with coordinates added along the edges.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use dynchess_lib::ChessPiece;
+use graphics::types::Color;
+use resvg::{tiny_skia, usvg};
+
+use crate::annotations::Annotations;
+use crate::chess_graphics::{ChessGraphicsSettings, FONT, SPRITES};
+
+/// What a diagram shows.
+pub struct Diagram {
+    /// Pieces indexed by square, a1 = 0 and h8 = 63.
+    pub board: [ChessPiece; 64],
+    /// Square highlighted like the selected square on screen.
+    pub highlighted_square: Option<u8>,
+    /// Marked squares and arrows.
+    pub annotations: Annotations,
+}
+
+/// Formats the color part of a `Color` for SVG.
+fn rgb(color: Color) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("rgb({},{},{})", channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+/// Returns the sprite of a piece, read from the override directory if one is set.
+fn sprite(settings: &ChessGraphicsSettings, file_name: &str, embedded: &[u8]) -> Vec<u8> {
+    settings.sprites_dir
+        .as_ref()
+        .and_then(|dir| fs::read(dir.join(file_name)).ok())
+        .unwrap_or_else(|| embedded.to_vec())
+}
+
+/// Renders a diagram as an SVG document.
+pub fn to_svg(diagram: &Diagram, settings: &ChessGraphicsSettings) -> String {
+    let side = settings.square_side;
+    let [x, y] = settings.offset;
+    let size = settings.size;
+    let mut svg = String::new();
+
+    // The view box is the board area of the window, so window coordinates can be used as they are
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{size}" height="{size}" viewBox="{x} {y} {size} {size}">"#,
+    );
+
+    // Every sprite is embedded once and placed with `use`
+    svg.push_str("<defs>\n");
+    for (piece, file_name, embedded) in SPRITES {
+        if diagram.board.contains(&piece) {
+            let data = BASE64.encode(sprite(settings, file_name, embedded));
+            let _ = writeln!(
+                svg,
+                r#"<image id="{}" width="{side}" height="{side}" xlink:href="data:image/png;base64,{data}"/>"#,
+                file_name.trim_end_matches(".png"),
+            );
+        }
+    }
+    svg.push_str("</defs>\n");
+
+    for square in 0..64u8 {
+        let [square_x, square_y, _, _] = settings.square_rect(square);
+        let color = if diagram.highlighted_square == Some(square) {
+            settings.selected_square_background_color
+        } else if settings.is_light_square(square) {
+            settings.white_color
+        } else {
+            settings.black_color
+        };
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{square_x}" y="{square_y}" width="{side}" height="{side}" fill="{}"/>"#,
+            rgb(color),
+        );
+
+        // Files along the bottom edge and ranks along the left edge, in the other square color
+        let label_color = if settings.is_light_square(square) {
+            settings.black_color
+        } else {
+            settings.white_color
+        };
+        let font_size = side * 0.2;
+        if square / 8 == 0 {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-family="DejaVu Sans" font-size="{font_size}" text-anchor="end" fill="{}">{}</text>"#,
+                square_x + side * 0.95,
+                square_y + side * 0.95,
+                rgb(label_color),
+                (b'a' + square % 8) as char,
+            );
+        }
+        if square % 8 == 0 {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-family="DejaVu Sans" font-size="{font_size}" fill="{}">{}</text>"#,
+                square_x + side * 0.05,
+                square_y + side * 0.22,
+                rgb(label_color),
+                square / 8 + 1,
+            );
+        }
+    }
+
+    for (square, piece) in diagram.board.iter().enumerate() {
+        if let Some((_, file_name, _)) = SPRITES.iter().find(|(sprite_piece, _, _)| sprite_piece == piece) {
+            let [square_x, square_y, _, _] = settings.square_rect(square as u8);
+            let _ = writeln!(
+                svg,
+                r##"<use xlink:href="#{}" x="{square_x}" y="{square_y}"/>"##,
+                file_name.trim_end_matches(".png"),
+            );
+        }
+    }
+
+    for (square, mark_color) in &diagram.annotations.squares {
+        let color = settings.annotation_colors[*mark_color as usize];
+        let [center_x, center_y] = settings.square_center(*square);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{center_x}" cy="{center_y}" r="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{}"/>"#,
+            side * 0.425,
+            rgb(color),
+            color[3],
+            side * 0.05,
+        );
+    }
+
+    for arrow in &diagram.annotations.arrows {
+        let color = settings.annotation_colors[arrow.color as usize];
+        let [from_x, from_y] = settings.square_center(arrow.from);
+        let [to_x, to_y] = settings.square_center(arrow.to);
+        let length = ((to_x - from_x).powi(2) + (to_y - from_y).powi(2)).sqrt();
+        let (dir_x, dir_y) = ((to_x - from_x) / length, (to_y - from_y) / length);
+        let head = side * 0.3;
+        // The shaft stops where the head starts so the translucent parts don't overlap
+        let (base_x, base_y) = (to_x - dir_x * head, to_y - dir_y * head);
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{from_x}" y1="{from_y}" x2="{base_x}" y2="{base_y}" stroke="{}" stroke-opacity="{}" stroke-width="{}" stroke-linecap="round"/>"#,
+            rgb(color),
+            color[3],
+            side * 0.16,
+        );
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{to_x},{to_y} {},{} {},{}" fill="{}" fill-opacity="{}"/>"#,
+            base_x - dir_y * head * 0.6,
+            base_y + dir_x * head * 0.6,
+            base_x + dir_y * head * 0.6,
+            base_y - dir_x * head * 0.6,
+            rgb(color),
+            color[3],
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Renders a diagram as a PNG image.
+pub fn to_png(diagram: &Diagram, settings: &ChessGraphicsSettings) -> io::Result<Vec<u8>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_font_data(FONT.to_vec());
+    let tree = usvg::Tree::from_str(&to_svg(diagram, settings), &options)
+        .map_err(|e| invalid(e.to_string()))?;
+
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| invalid(String::from("Diagram has no size")))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| invalid(e.to_string()))
+}
+
+/// Saves a diagram as SVG or PNG, depending on the extension of `path`.
+pub fn save(diagram: &Diagram, settings: &ChessGraphicsSettings, path: &Path) -> io::Result<()> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("svg") => fs::write(path, to_svg(diagram, settings)),
+        Some("png") => fs::write(path, to_png(diagram, settings)?),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Diagrams can only be saved as .svg or .png",
+        )),
+    }
+}
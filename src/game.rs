@@ -0,0 +1,694 @@
+//! A game against an opponent, without any input handling or layout.
+//!
+//! Front-ends such as `ChessController` and the terminal turn input into moves for `Game::play`
+//! and call `Game::update` regularly, which plays the opponent's moves as they arrive.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dynchess_lib::ChessPiece;
+
+use crate::analysis::Analysis;
+use crate::announcer::{self, Announcer};
+use crate::chess_rules::{ChessMove, GameResult, PieceKind, Position, Side};
+use crate::networking::{Networking, State, TakebackMessage, MAX_CHAT_LENGTH};
+use crate::pgn::{self, GameMetadata};
+use crate::uci_engine::UciEngine;
+
+/// How long the engine thinks about each of its moves.
+const ENGINE_MOVE_TIME_MS: u64 = 1000;
+
+/// How many of the engine's best variations are shown while analysing.
+pub const ANALYSIS_LINES: usize = 3;
+
+/// A move that has been played.
+pub struct PlayedMove {
+    pub chess_move: ChessMove,
+    /// The move in Standard Algebraic Notation.
+    pub san: String,
+    /// How long the side that played the move thought about it.
+    pub elapsed: Duration,
+}
+
+/// A chat message, from either player or a spectator.
+pub struct ChatLine {
+    /// Name of whoever wrote the message.
+    pub sender: String,
+    pub text: String,
+}
+
+/// Who the player is playing against.
+pub enum Opponent {
+    /// Another player over the network.
+    Network(Networking),
+    /// Both sides are played on this board.
+    Local,
+    /// A UCI engine playing Black.
+    Engine(UciEngine),
+}
+
+/// The state of a game and the opponent it is played against.
+pub struct Game {
+    /// Position the game started from, the initial position unless it was set up in the board
+    /// editor.
+    pub start: Position,
+    /// The board as it is now.
+    pub position: Position,
+    /// Moves played so far.
+    pub history: Vec<PlayedMove>,
+    /// Moves the player queued during the opponent's turn, played one per turn as soon as the
+    /// opponent has moved.
+    pub premoves: Vec<ChessMove>,
+    /// Shown in the status bar until the next move, e.g. where a diagram was saved.
+    pub notice: Option<String>,
+    /// Engine analysing the position, while analysis is turned on.
+    pub analysis: Option<Analysis>,
+    /// Command that starts the engine used for analysis.
+    pub analysis_command: String,
+    /// Describes every move in words, for screen readers.
+    pub announcer: Option<Announcer>,
+    /// Who the player is playing against.
+    pub opponent: Opponent,
+    /// Players, date and other tags written to PGN.
+    pub metadata: GameMetadata,
+    /// PGN file the game is saved to after every move, network games are saved for review.
+    pub autosave: Option<PathBuf>,
+    /// Half moves left if the takeback the opponent asked for is accepted, until it is answered.
+    pub takeback_request: Option<usize>,
+    /// Half moves left if the opponent accepts the player's takeback request, until they answer.
+    takeback_pending: Option<usize>,
+    /// Chat messages sent and received during a network game, oldest first.
+    pub chat: Vec<ChatLine>,
+    /// When the side to move started thinking.
+    turn_started: Instant,
+}
+
+impl Game {
+    /// Starts a game from the initial position.
+    pub fn new(opponent: Opponent) -> Game {
+        Game::from_position(opponent, Position::initial())
+    }
+
+    /// Starts a game from `start`, which should be valid, see `Position::validate`.
+    ///
+    /// When hosting a network game, `start` is sent to the client once it connects.
+    pub fn from_position(mut opponent: Opponent, start: Position) -> Game {
+        if let Opponent::Network(networking) = &mut opponent {
+            if networking.is_host() && start != Position::initial() {
+                networking.starting_position = Some(start.to_fen());
+                // The host plays White, so the client moves first if Black is to move
+                if start.side_to_move == Side::Black {
+                    networking.state = State::WaitingForOpponent;
+                }
+            }
+        }
+
+        // The host plays White against a client, and the player is White against an engine
+        let metadata = match &opponent {
+            Opponent::Network(networking) => {
+                let mut metadata = GameMetadata::new("Network game");
+                if let Some(peer) = networking.peer() {
+                    metadata.site = peer;
+                }
+                if networking.is_host() {
+                    metadata.white = pgn::player_name();
+                } else {
+                    metadata.black = pgn::player_name();
+                }
+                metadata
+            }
+            Opponent::Local => GameMetadata::new("Local game"),
+            Opponent::Engine(engine) => {
+                let mut metadata = GameMetadata::new("Engine game");
+                metadata.white = pgn::player_name();
+                metadata.black = engine.name.clone();
+                metadata
+            }
+        };
+        let autosave = match &opponent {
+            Opponent::Network(networking) if networking.is_host() => Some(pgn::autosave_path("host")),
+            Opponent::Network(_) => Some(pgn::autosave_path("client")),
+            _ => None,
+        };
+
+        Game {
+            start: start.clone(),
+            position: start,
+            history: Vec::new(),
+            premoves: Vec::new(),
+            notice: None,
+            analysis: None,
+            analysis_command: std::env::var("CHESS_ANALYSIS_ENGINE").unwrap_or_else(|_| String::from("stockfish")),
+            announcer: Announcer::from_env(),
+            opponent,
+            metadata,
+            autosave,
+            takeback_request: None,
+            takeback_pending: None,
+            chat: Vec::new(),
+            turn_started: Instant::now(),
+        }
+    }
+
+    /// Handles the connection handshake, plays the opponent's moves once they arrive and picks up
+    /// new analysis. Never blocks.
+    pub fn update(&mut self) {
+        if let Some(analysis) = &mut self.analysis {
+            analysis.update();
+            if analysis.has_failed() {
+                self.analysis = None;
+                self.notice = Some(String::from("Analysis engine stopped"));
+            }
+        }
+
+        let received_start = match &mut self.opponent {
+            Opponent::Network(networking) if !networking.is_host() => networking.starting_position.take(),
+            _ => None,
+        };
+        if let Some(fen) = received_start {
+            self.restart_from_fen(&fen);
+        }
+
+        match &mut self.opponent {
+            // Nothing more is read once the handshake has failed
+            Opponent::Network(networking) if networking.rejection.is_some() => {}
+            Opponent::Network(networking) => {
+                // Spectators aren't served until the handshake has finished
+                if networking.is_connected() {
+                    if let Err(e) = networking.serve_spectators(&self.position.to_fen()) {
+                        self.notice = Some(format!("Could not pass a spectator's message on: {}", e));
+                    }
+                }
+
+                // Takeback and chat messages can arrive during either turn, so the socket is always
+                // read
+                let received = networking.receive_packet();
+                let takeback = networking.takeback.take();
+                self.chat.extend(networking.chat.drain(..).map(|chat| ChatLine {
+                    sender: chat.sender,
+                    text: chat.text,
+                }));
+                if let Some(chess_move) = received {
+                    if !self.apply_move(chess_move) {
+                        self.notice = Some(format!("The opponent sent an illegal move: {}", chess_move.uci()));
+                    }
+                    self.play_premove();
+                }
+                if let Some(takeback) = takeback {
+                    self.receive_takeback(takeback);
+                }
+            }
+            Opponent::Local => {}
+            Opponent::Engine(engine) => {
+                if self.position.side_to_move == Side::White || engine.has_failed() {
+                    return;
+                }
+                let best_move = engine.poll_best_move();
+                if engine.has_failed() {
+                    self.notice = Some(String::from("The engine stopped"));
+                } else if let Some(chess_move) = best_move {
+                    // Asking again would only get the same move back
+                    if !self.position.is_legal(chess_move) {
+                        engine.fail();
+                        self.notice = Some(format!("The engine played an illegal move: {}", chess_move.uci()));
+                        return;
+                    }
+                    self.apply_move(chess_move);
+                    self.play_premove();
+                } else if !engine.is_searching() && self.position.result().is_none() {
+                    let moves: Vec<ChessMove> = self.history.iter().map(|played| played.chess_move).collect();
+                    if let Err(e) = engine.go(&self.start, &moves, ENGINE_MOVE_TIME_MS) {
+                        self.notice = Some(format!("Could not send the position to the engine: {}", e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether the player on this side of the game may move.
+    pub fn is_players_turn(&self) -> bool {
+        match &self.opponent {
+            Opponent::Network(networking) => networking.is_connected() && networking.state == State::Playing,
+            Opponent::Local => true,
+            Opponent::Engine(_) => self.position.side_to_move == Side::White,
+        }
+    }
+
+    /// Plays the player's move and sends it to a network opponent.
+    ///
+    /// Returns `false` without changing anything if it isn't the player's turn or the move is
+    /// illegal.
+    pub fn play(&mut self, chess_move: ChessMove) -> bool {
+        if !self.is_players_turn() {
+            return false;
+        }
+        // Builds without the capability read every promotion as a queen
+        let underpromotion = matches!(chess_move.promotion, Some(kind) if kind != PieceKind::Queen);
+        if let Opponent::Network(networking) = &self.opponent {
+            if underpromotion && !networking.supports("promotion") {
+                self.notice = Some(String::from("The opponent's build can only promote to a queen"));
+                return false;
+            }
+        }
+        if !self.apply_move(chess_move) {
+            return false;
+        }
+
+        // The move as played, with the piece a pawn promotes to
+        let played = self.history.last().map(|played| played.chess_move);
+        if let Opponent::Network(networking) = &mut self.opponent {
+            let sent = networking.send_packet(played);
+            networking.state = State::WaitingForOpponent;
+            if let Err(e) = sent {
+                self.notice = Some(format!("Could not send the move: {}", e));
+            }
+        }
+        true
+    }
+
+    /// Returns whether the player can queue premoves, which is during the opponent's turn once
+    /// connected.
+    pub fn can_premove(&self) -> bool {
+        let connected = match &self.opponent {
+            Opponent::Network(networking) => networking.is_connected(),
+            _ => true,
+        };
+        connected && self.player_side().is_some() && !self.is_players_turn() && self.position.result().is_none()
+    }
+
+    /// Queues a move to play once it is the player's turn.
+    ///
+    /// Premoves are only checked when they are played, as the opponent's moves aren't known yet.
+    pub fn premove(&mut self, chess_move: ChessMove) {
+        self.premoves.push(chess_move);
+    }
+
+    /// Cancels every queued premove.
+    pub fn cancel_premoves(&mut self) {
+        self.premoves.clear();
+    }
+
+    /// Returns the board with the queued premoves played on it, without checking them, which shows
+    /// where the player's pieces will be for queueing more premoves.
+    pub fn premove_board(&self) -> [ChessPiece; 64] {
+        let mut board = self.position.board;
+        for premove in &self.premoves {
+            board[premove.to as usize] = board[premove.from as usize];
+            board[premove.from as usize] = ChessPiece::Empty;
+        }
+        board
+    }
+
+    /// Plays the first queued premove if it is the player's turn, cancelling every premove if it
+    /// has become illegal.
+    fn play_premove(&mut self) {
+        if self.premoves.is_empty() || !self.is_players_turn() {
+            return;
+        }
+        let premove = self.premoves.remove(0);
+        if !self.play(premove) {
+            self.premoves.clear();
+            self.notice = Some(format!("Premove {} is illegal, premoves cancelled", premove.uci()));
+        }
+    }
+
+    /// Plays a move typed in SAN or UCI notation, or sets a notice saying why it can't be played.
+    pub fn play_typed(&mut self, text: &str) -> Option<ChessMove> {
+        if !self.is_players_turn() {
+            self.notice = Some(String::from("Wait for your turn"));
+            return None;
+        }
+        match self.position.parse_move(text) {
+            Some(chess_move) if self.play(chess_move) => Some(chess_move),
+            _ => {
+                self.notice = Some(format!("Illegal move: {}", text));
+                None
+            }
+        }
+    }
+
+    /// Takes back the player's last move, and the opponent's reply if they have made one, or the
+    /// last move when both sides are played locally.
+    ///
+    /// Over the network, the opponent is asked first and the moves are taken back once they
+    /// accept.
+    pub fn takeback(&mut self) {
+        let ply = match self.player_side() {
+            Some(side) => (0..self.history.len()).rev().find(|ply| self.side_of_move(*ply) == side),
+            None => self.history.len().checked_sub(1),
+        };
+        let ply = match ply {
+            Some(ply) => ply,
+            None => {
+                self.notice = Some(String::from("No move to take back"));
+                return;
+            }
+        };
+
+        match &mut self.opponent {
+            Opponent::Network(networking) if !networking.supports("takeback") => {
+                self.notice = Some(String::from("The opponent's build doesn't support takebacks"));
+            }
+            Opponent::Network(_) if self.takeback_pending.is_some() => {
+                self.notice = Some(String::from("Waiting for the opponent to answer the takeback"));
+            }
+            Opponent::Network(networking) => match networking.send_takeback(TakebackMessage::Request { ply: ply as u32 }) {
+                Ok(()) => {
+                    self.takeback_pending = Some(ply);
+                    self.notice = Some(String::from("Asked the opponent to take back your move"));
+                }
+                Err(e) => self.notice = Some(format!("Could not ask for a takeback: {}", e)),
+            },
+            // Its move would be for the position before the takeback
+            Opponent::Engine(engine) if engine.is_searching() => {
+                self.notice = Some(String::from("Wait for the engine to move"));
+            }
+            Opponent::Engine(_) | Opponent::Local => self.take_back_to(ply),
+        }
+    }
+
+    /// Answers the opponent's takeback request, taking the moves back if `accepted`.
+    pub fn answer_takeback(&mut self, accepted: bool) {
+        let ply = match self.takeback_request.take() {
+            Some(ply) => ply,
+            None => return,
+        };
+        if let Opponent::Network(networking) = &mut self.opponent {
+            // The moves stay on the board if the opponent can't be told they were taken back
+            if let Err(e) = networking.send_takeback(TakebackMessage::Response { accepted }) {
+                self.notice = Some(format!("Could not answer the takeback: {}", e));
+                return;
+            }
+        }
+        if accepted {
+            self.take_back_to(ply);
+        }
+    }
+
+    /// Describes the opponent's takeback request for the prompt, while it is unanswered.
+    pub fn takeback_prompt(&self) -> Option<String> {
+        let ply = self.takeback_request?;
+        let moves: Vec<&str> = self.history[ply..].iter().map(|played| played.san.as_str()).collect();
+        Some(format!("Your opponent asks to take back {}", moves.join(" ")))
+    }
+
+    /// Handles a takeback request or answer from a network opponent.
+    fn receive_takeback(&mut self, takeback: TakebackMessage) {
+        match takeback {
+            TakebackMessage::Request { ply } if (ply as usize) < self.history.len() => {
+                self.takeback_request = Some(ply as usize);
+            }
+            // Nothing to take back, e.g. the moves were taken back already
+            TakebackMessage::Request { .. } => {
+                if let Opponent::Network(networking) = &mut self.opponent {
+                    if let Err(e) = networking.send_takeback(TakebackMessage::Response { accepted: false }) {
+                        self.notice = Some(format!("Could not answer the takeback: {}", e));
+                    }
+                }
+            }
+            TakebackMessage::Response { accepted } => match self.takeback_pending.take() {
+                Some(ply) if accepted => {
+                    self.take_back_to(ply);
+                    self.notice = Some(String::from("The opponent accepted the takeback"));
+                }
+                Some(_) => self.notice = Some(String::from("The opponent declined the takeback")),
+                None => {}
+            },
+        }
+    }
+
+    /// Returns whether the game has a chat, which network games do when the opponent's build
+    /// supports it.
+    pub fn has_chat(&self) -> bool {
+        matches!(&self.opponent, Opponent::Network(networking) if networking.supports("chat"))
+    }
+
+    /// Sends a chat message to the opponent, and the spectators when hosting.
+    pub fn send_chat(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        // Players who haven't set a name are told apart by their side
+        let sender = match (pgn::player_name(), self.player_side()) {
+            (name, Some(Side::White)) if name == "?" => String::from("White"),
+            (name, Some(Side::Black)) if name == "?" => String::from("Black"),
+            (name, _) => name,
+        };
+        if let Opponent::Network(networking) = &mut self.opponent {
+            match networking.send_chat(&sender, text) {
+                Ok(()) => self.chat.push(ChatLine { sender, text: text.chars().take(MAX_CHAT_LENGTH).collect() }),
+                Err(e) => self.notice = Some(format!("Could not send the message: {}", e)),
+            }
+        }
+    }
+
+    /// Returns the side that played the move at `ply` in the history.
+    fn side_of_move(&self, ply: usize) -> Side {
+        if ply % 2 == 1 {
+            self.start.side_to_move.opponent()
+        } else {
+            self.start.side_to_move
+        }
+    }
+
+    /// Takes back every move after the first `ply` half moves.
+    pub fn take_back_to(&mut self, ply: usize) {
+        self.history.truncate(ply);
+        self.position = self.start.clone();
+        for played in &self.history {
+            self.position.make_move(played.chess_move);
+        }
+        self.premoves.clear();
+        self.notice = None;
+        self.turn_started = Instant::now();
+
+        let players_turn = self.player_side() == Some(self.position.side_to_move);
+        if let Opponent::Network(networking) = &mut self.opponent {
+            networking.state = if players_turn {
+                State::Playing
+            } else {
+                State::WaitingForOpponent
+            };
+        }
+        self.analyse();
+        self.write_autosave();
+    }
+
+    /// Saves the game as PGN in the working directory.
+    pub fn save_pgn(&mut self) {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = PathBuf::from(format!("game-{}.pgn", seconds));
+
+        self.notice = Some(match pgn::save(self, &path) {
+            Ok(()) => format!("Saved game to {}", path.display()),
+            Err(e) => format!("Could not save game: {}", e),
+        });
+    }
+
+    /// Starts analysing the position with the analysis engine, or stops if it is running.
+    pub fn toggle_analysis(&mut self) {
+        if self.analysis.take().is_some() {
+            return;
+        }
+
+        match Analysis::start(&self.analysis_command, ANALYSIS_LINES) {
+            Ok(analysis) => {
+                self.analysis = Some(analysis);
+                self.analyse();
+            }
+            Err(e) => {
+                self.notice = Some(format!("Could not start analysis engine '{}': {}", self.analysis_command, e));
+            }
+        }
+    }
+
+    /// Points the analysis engine at the current position.
+    pub fn analyse(&mut self) {
+        let moves: Vec<ChessMove> = self.history.iter().map(|played| played.chess_move).collect();
+        if let Some(analysis) = &mut self.analysis {
+            if let Err(e) = analysis.analyse(&self.position, &self.start, &moves) {
+                self.analysis = None;
+                self.notice = Some(format!("Analysis engine stopped: {}", e));
+            }
+        }
+    }
+
+    /// Starts over from the position the host sent, before any move has been played.
+    fn restart_from_fen(&mut self, fen: &str) {
+        let start = match Position::from_fen(fen).and_then(|start| start.validate().map(|()| start)) {
+            Ok(start) => start,
+            Err(e) => {
+                self.notice = Some(format!("Host sent an invalid starting position: {}", e));
+                return;
+            }
+        };
+
+        // The client plays Black, so it moves first if Black is to move
+        if let Opponent::Network(networking) = &mut self.opponent {
+            networking.state = if start.side_to_move == Side::Black {
+                State::Playing
+            } else {
+                State::WaitingForOpponent
+            };
+        }
+        self.restart(start);
+    }
+
+    /// Starts over from `start`, e.g. a position pasted as FEN, in a local game or against the
+    /// engine.
+    ///
+    /// Returns why not, without changing anything, if `start` isn't valid or the game is played
+    /// over the network, where both sides would have to agree.
+    pub fn set_up(&mut self, start: Position) -> Result<(), String> {
+        match &self.opponent {
+            Opponent::Network(_) => return Err(String::from("Can't set up a position during a network game")),
+            // Its move would be for the old position
+            Opponent::Engine(engine) if engine.is_searching() => return Err(String::from("Wait for the engine to move")),
+            _ => {}
+        }
+        start.validate()?;
+        self.restart(start);
+        Ok(())
+    }
+
+    /// Replaces the game with one starting from `start`.
+    fn restart(&mut self, start: Position) {
+        self.start = start.clone();
+        self.position = start;
+        self.history.clear();
+        self.premoves.clear();
+        self.takeback_request = None;
+        self.takeback_pending = None;
+        self.turn_started = Instant::now();
+        self.analyse();
+    }
+
+    /// Returns the pieces captured so far in the order they were taken, found by replaying the
+    /// moves from the start position, so set-up positions and promotions are accounted for.
+    pub fn captured_pieces(&self) -> Vec<ChessPiece> {
+        let mut position = self.start.clone();
+        let mut captured = Vec::new();
+        for played in &self.history {
+            captured.extend(position.captured_piece(played.chess_move));
+            position.make_move(played.chess_move);
+        }
+        captured
+    }
+
+    /// Returns 1 if the game started with Black to move, so a move list has no move for White in
+    /// its first row, and 0 otherwise.
+    pub fn first_move_offset(&self) -> usize {
+        match self.start.side_to_move {
+            Side::White => 0,
+            Side::Black => 1,
+        }
+    }
+
+    /// Returns the full move number of the move at index `ply` of the history, counted from the
+    /// start position's.
+    pub fn move_number(&self, ply: usize) -> u32 {
+        self.start.fullmove_number + ((ply + self.first_move_offset()) / 2) as u32
+    }
+
+    /// Returns the side this player plays, or `None` when both sides are played locally.
+    ///
+    /// The host plays White against a client, and the player is White against an engine.
+    pub fn player_side(&self) -> Option<Side> {
+        match &self.opponent {
+            Opponent::Network(networking) if !networking.is_host() => Some(Side::Black),
+            Opponent::Network(_) | Opponent::Engine(_) => Some(Side::White),
+            Opponent::Local => None,
+        }
+    }
+
+    /// Describes the connection and the state of the game for the status bar.
+    pub fn status_text(&self) -> String {
+        let side_name = |side: Side| match side {
+            Side::White => "White",
+            Side::Black => "Black",
+        };
+        let game = match self.position.result() {
+            Some(GameResult::Checkmate { winner }) => format!("Checkmate, {} wins", side_name(winner)),
+            Some(GameResult::Stalemate) => String::from("Draw by stalemate"),
+            Some(GameResult::InsufficientMaterial) => String::from("Draw by insufficient material"),
+            Some(GameResult::FiftyMoveRule) => String::from("Draw by the fifty-move rule"),
+            None => {
+                let side = self.position.side_to_move;
+                let check = if self.position.in_check() { ", check" } else { "" };
+                match self.player_side() {
+                    Some(player) if player == side => format!("Your turn ({}){}", side_name(side), check),
+                    Some(_) => format!("Opponent's turn ({}){}", side_name(side), check),
+                    None => format!("{} to move{}", side_name(side), check),
+                }
+            }
+        };
+
+        let game = match &self.notice {
+            Some(notice) => format!("{}  |  {}", game, notice),
+            None => game,
+        };
+
+        match &self.opponent {
+            Opponent::Network(networking) => {
+                let peer = networking.peer().unwrap_or_else(|| String::from("unknown address"));
+
+                if let Some(rejection) = &networking.rejection {
+                    return format!("Not connected to {}  |  {}", peer, rejection);
+                }
+                if !networking.is_connected() {
+                    return if networking.is_host() {
+                        format!("Waiting for {} to join game {}", peer, networking.game_id)
+                    } else {
+                        format!("Connecting to {}, waiting for the host to accept", peer)
+                    };
+                }
+
+                format!("Connected to {}  |  Game {}  |  {}", peer, networking.game_id, game)
+            }
+            Opponent::Local => format!("Local game  |  {}", game),
+            Opponent::Engine(_) => format!("Playing the engine  |  {}", game),
+        }
+    }
+
+    /// Plays a move on the board and adds it to the history.
+    ///
+    /// Returns `false` without changing anything if the move is illegal.
+    pub fn apply_move(&mut self, chess_move: ChessMove) -> bool {
+        if !self.position.is_legal(chess_move) {
+            return false;
+        }
+
+        let chess_move = self.position.normalize(chess_move);
+        let san = self.position.san(chess_move);
+        let announcement = self.announcer
+            .as_ref()
+            .map(|_| announcer::describe_move(&self.position, chess_move));
+        self.position.make_move(chess_move);
+        let elapsed = self.turn_started.elapsed();
+        self.turn_started = Instant::now();
+        self.history.push(PlayedMove { chess_move, san, elapsed });
+        self.notice = None;
+        self.analyse();
+        self.write_autosave();
+        if let (Some(announcer), Some(announcement)) = (&mut self.announcer, announcement) {
+            if let Err(e) = announcer.announce(&announcement) {
+                self.announcer = None;
+                self.notice = Some(format!("Stopped announcing moves: {}", e));
+            }
+        }
+        true
+    }
+
+    /// Saves the game to the autosave file, if there is one.
+    fn write_autosave(&mut self) {
+        if let Some(path) = &self.autosave {
+            if let Err(e) = pgn::save(self, path) {
+                self.notice = Some(format!("Stopped saving the game to {}: {}", path.display(), e));
+                self.autosave = None;
+            }
+        }
+    }
+}
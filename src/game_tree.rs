@@ -0,0 +1,216 @@
+//! Every line played in a game, the main line and the variations branching off it, so moves can
+//! be undone, redone and jumped between without losing any of them.
+
+use crate::chess_rules::ChessMove;
+use crate::game::PlayedMove;
+
+/// A move in the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveNode {
+    pub chess_move: ChessMove,
+    /// The move in Standard Algebraic Notation.
+    pub san: String,
+    /// Moves played from the position after this one, the first is the main line.
+    children: Vec<usize>,
+    /// Child redo follows, the one visited last.
+    next: Option<usize>,
+}
+
+/// Tree of moves played from the start position.
+///
+/// The game's history is the line being viewed, and `GameTree::record` adds it to the tree after
+/// every change, so playing a different move from a past position starts a new variation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GameTree {
+    nodes: Vec<MoveNode>,
+    /// Moves played from the start position, the first is the main line.
+    first_moves: Vec<usize>,
+    /// First move redo follows from the start position.
+    next: Option<usize>,
+}
+
+impl GameTree {
+    /// Creates an empty tree.
+    pub fn new() -> GameTree {
+        GameTree::default()
+    }
+
+    /// Adds the moves of `history` that aren't in the tree yet, and makes redo follow it.
+    pub fn record(&mut self, history: &[PlayedMove]) {
+        let mut parent = None;
+        for played in history {
+            let node = self.add(parent, played.chess_move, &played.san);
+            match parent {
+                Some(parent) => self.nodes[parent].next = Some(node),
+                None => self.next = Some(node),
+            }
+            parent = Some(node);
+        }
+    }
+
+    /// Adds `chess_move` after `parent`, `None` standing for the start, unless it is there already,
+    /// without changing what redo follows. Returns its node, to add the moves after it.
+    pub fn add(&mut self, parent: Option<usize>, chess_move: ChessMove, san: &str) -> usize {
+        if let Some(node) = self.child(parent, chess_move) {
+            return node;
+        }
+        self.nodes.push(MoveNode {
+            chess_move,
+            san: san.to_string(),
+            children: Vec::new(),
+            next: None,
+        });
+        let node = self.nodes.len() - 1;
+        self.children_mut(parent).push(node);
+        node
+    }
+
+    /// Returns the moves redo plays one after the other from the end of `history`.
+    pub fn continuation(&self, history: &[PlayedMove]) -> Vec<&MoveNode> {
+        let mut moves = Vec::new();
+        let mut node = match self.find(history) {
+            Some(parent) => self.next_of(parent),
+            None => return moves,
+        };
+        while let Some(index) = node {
+            moves.push(&self.nodes[index]);
+            node = self.next_of(Some(index));
+        }
+        moves
+    }
+
+    /// Returns the moves played instead of the last move of `history`, including it, in the order
+    /// they were first played.
+    pub fn alternatives(&self, history: &[PlayedMove]) -> Vec<&MoveNode> {
+        let parent = match history.split_last() {
+            Some((_, before)) => self.find(before),
+            None => None,
+        };
+        match parent {
+            Some(parent) => self.children(parent).iter().map(|index| &self.nodes[*index]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the node `history` ends on, `Some(None)` at the start position, or `None` if it
+    /// isn't in the tree.
+    fn find(&self, history: &[PlayedMove]) -> Option<Option<usize>> {
+        let mut node = None;
+        for played in history {
+            node = Some(self.child(node, played.chess_move)?);
+        }
+        Some(node)
+    }
+
+    /// Returns the child of `parent` that plays `chess_move`, `None` standing for the start.
+    fn child(&self, parent: Option<usize>, chess_move: ChessMove) -> Option<usize> {
+        self.children(parent).iter().copied().find(|index| self.nodes[*index].chess_move == chess_move)
+    }
+
+    /// Returns the child redo follows from `parent`, the main line if it hasn't been visited.
+    fn next_of(&self, parent: Option<usize>) -> Option<usize> {
+        let next = match parent {
+            Some(parent) => self.nodes[parent].next,
+            None => self.next,
+        };
+        next.or_else(|| self.children(parent).first().copied())
+    }
+
+    fn children(&self, parent: Option<usize>) -> &[usize] {
+        match parent {
+            Some(parent) => &self.nodes[parent].children,
+            None => &self.first_moves,
+        }
+    }
+
+    fn children_mut(&mut self, parent: Option<usize>) -> &mut Vec<usize> {
+        match parent {
+            Some(parent) => &mut self.nodes[parent].children,
+            None => &mut self.first_moves,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chess_rules::Position;
+
+    /// Returns the moves given in UCI notation as played from the start position.
+    fn line(moves: &[&str]) -> Vec<PlayedMove> {
+        let mut position = Position::initial();
+        moves
+            .iter()
+            .map(|uci| {
+                let chess_move = ChessMove::from_uci(uci).unwrap();
+                let san = position.san(chess_move);
+                position.make_move(chess_move);
+                PlayedMove { chess_move, san, elapsed: Duration::ZERO }
+            })
+            .collect()
+    }
+
+    fn sans(nodes: Vec<&MoveNode>) -> Vec<&str> {
+        nodes.iter().map(|node| node.san.as_str()).collect()
+    }
+
+    #[test]
+    fn recording_the_same_line_again_adds_nothing() {
+        let mut tree = GameTree::new();
+        tree.record(&line(&["e2e4", "e7e5"]));
+        tree.record(&line(&["e2e4"]));
+        tree.record(&line(&["e2e4", "e7e5"]));
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(sans(tree.continuation(&[])), ["e4", "e5"]);
+    }
+
+    #[test]
+    fn a_different_move_starts_a_variation() {
+        let mut tree = GameTree::new();
+        tree.record(&line(&["e2e4", "e7e5", "g1f3"]));
+        tree.record(&line(&["e2e4", "c7c5"]));
+
+        assert_eq!(sans(tree.alternatives(&line(&["e2e4", "e7e5"]))), ["e5", "c5"]);
+        assert_eq!(sans(tree.alternatives(&line(&["e2e4", "c7c5"]))), ["e5", "c5"]);
+        // The main line keeps its moves
+        assert_eq!(sans(tree.continuation(&line(&["e2e4", "e7e5"]))), ["Nf3"]);
+        assert_eq!(sans(tree.alternatives(&line(&["e2e4"]))), ["e4"]);
+    }
+
+    #[test]
+    fn redo_follows_the_variation_played_last() {
+        let mut tree = GameTree::new();
+        tree.record(&line(&["e2e4", "e7e5", "g1f3"]));
+        tree.record(&line(&["e2e4", "c7c5", "g1f3"]));
+        assert_eq!(sans(tree.continuation(&line(&["e2e4"]))), ["c5", "Nf3"]);
+
+        // Going back into the main line makes redo follow it again
+        tree.record(&line(&["e2e4", "e7e5"]));
+        assert_eq!(sans(tree.continuation(&line(&["e2e4"]))), ["e5", "Nf3"]);
+        assert_eq!(sans(tree.continuation(&[])), ["e4", "e5", "Nf3"]);
+        // The variation keeps its place after the main line
+        assert_eq!(sans(tree.alternatives(&line(&["e2e4", "c7c5"]))), ["e5", "c5"]);
+    }
+
+    #[test]
+    fn navigating_off_the_tree_finds_nothing() {
+        let mut tree = GameTree::new();
+        tree.record(&line(&["e2e4", "e7e5"]));
+        assert!(tree.continuation(&line(&["d2d4"])).is_empty());
+        assert!(tree.alternatives(&line(&["d2d4", "d7d5"])).is_empty());
+        assert!(tree.alternatives(&[]).is_empty());
+        assert!(tree.continuation(&line(&["e2e4", "e7e5"])).is_empty());
+    }
+
+    #[test]
+    fn added_moves_dont_change_what_redo_follows() {
+        let mut tree = GameTree::new();
+        let e4 = tree.add(None, ChessMove::from_uci("e2e4").unwrap(), "e4");
+        tree.add(Some(e4), ChessMove::from_uci("e7e5").unwrap(), "e5");
+        tree.add(Some(e4), ChessMove::from_uci("c7c5").unwrap(), "c5");
+        assert_eq!(tree.add(None, ChessMove::from_uci("e2e4").unwrap(), "e4"), e4);
+        assert_eq!(sans(tree.continuation(&[])), ["e4", "e5"]);
+    }
+}
@@ -0,0 +1,36 @@
+//! Chess GUI library, the game logic, front-ends and networking shared by the `chess_gui` binary
+//! and the integration tests.
+
+pub use crate::board_editor::{BoardEditor, EditorOutcome};
+pub use crate::chess_controller::ChessController;
+pub use crate::clipboard::{Clipboard, MemoryClipboard, SystemClipboard};
+pub use crate::game::{Game, Opponent};
+pub use crate::chess_graphics::{ChessGraphics, ChessGraphicsSettings};
+pub use crate::chess_rules::{ChessMove, Position};
+pub use crate::networking::{Networking, PendingConnection};
+pub use crate::replay::{GameChooser, ReplayViewer};
+pub use crate::software_graphics::{SoftwareGraphics, SoftwareTexture};
+pub use crate::start_menu::{GameMode, StartMenu, WaitingOutcome, WaitingScreen};
+pub use crate::transport::{ChannelTransport, TcpTransport, Transport, WebSocketTransport};
+pub use crate::uci_engine::UciEngine;
+
+pub mod analysis;
+pub mod annotations;
+pub mod announcer;
+pub mod board_editor;
+pub mod chess_controller;
+pub mod chess_graphics;
+pub mod chess_rules;
+pub mod clipboard;
+pub mod diagram;
+pub mod game;
+pub mod game_tree;
+pub mod networking;
+pub mod networking_protobuf;
+pub mod pgn;
+pub mod replay;
+pub mod software_graphics;
+pub mod start_menu;
+pub mod terminal;
+pub mod transport;
+pub mod uci_engine;
@@ -1,46 +1,229 @@
 extern crate core;
 
+use std::path::{Path, PathBuf};
 use glutin_window::GlutinWindow;
 use piston::event_loop::{EventSettings, Events};
-use piston::{EventLoop, RenderEvent, WindowSettings};
-use opengl_graphics::{OpenGL, GlGraphics};
+use piston::{Button, EventLoop, Key, PressEvent, RenderEvent, Window, WindowSettings};
+use opengl_graphics::{OpenGL, GlGraphics, Texture};
 use graphics::{clear};
 
-pub use crate::chess_controller::ChessController;
-pub use crate::chess_graphics::{ChessGraphics, ChessGraphicsSettings};
-pub use crate::networking::Networking;
+use chess_gui::{
+    diagram,
+    pgn,
+    terminal,
+    BoardEditor,
+    ChessController,
+    ChessGraphics,
+    ChessGraphicsSettings,
+    ChessMove,
+    EditorOutcome,
+    GameChooser,
+    GameMode,
+    Opponent,
+    PendingConnection,
+    Position,
+    ReplayViewer,
+    SoftwareGraphics,
+    SoftwareTexture,
+    StartMenu,
+    UciEngine,
+    WaitingOutcome,
+    WaitingScreen,
+};
 
-mod chess_controller;
-mod chess_graphics;
-mod networking;
-mod networking_protobuf;
+/// What the window shows.
+enum Screen {
+    Menu(StartMenu),
+    Waiting(WaitingScreen),
+    Editor(BoardEditor),
+    Game(ChessController),
+    Chooser(GameChooser),
+    Replay(ReplayViewer),
+}
 
-fn main() {
-    let opengl = OpenGL::V3_2;
+/// What to do, read from the command line.
+enum Command {
+    /// Open the window, on the start menu if there is no game mode.
+    Play(Option<GameMode>),
+    /// Save a diagram of the position after `moves`, in UCI notation, without opening a window.
+    Diagram { path: PathBuf, moves: Vec<String> },
+    /// Save a PNG of the whole window after `moves`, drawn without a GPU.
+    Screenshot { path: PathBuf, moves: Vec<String> },
+    /// Save `moves`, in UCI notation, as a PGN game.
+    Pgn { path: PathBuf, moves: Vec<String> },
+    /// Play in the terminal instead of the window.
+    Terminal(GameMode),
+}
+
+const USAGE: &str = "Usage: chess_gui [--terminal] [--host <address:port> | --client <address:port> | --local | --engine <command>]
+       chess_gui --replay <file.pgn>
+       chess_gui --diagram <file.svg|file.png> [moves...]
+       chess_gui --screenshot <file.png> [moves...]
+       chess_gui --pgn <file.pgn> [moves...]";
 
-    let mut args = std::env::args();
+const BACKGROUND_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+
+/// Reads what to do from the command line.
+fn parse_args() -> Result<Command, String> {
+    let mut args = std::env::args().peekable();
     // Skip path to program
     args.next();
+    let terminal = args.next_if_eq("--terminal").is_some();
 
     // Get first argument after path to program
-    let host_or_client = args
-        .next()
-        .expect("Expected arguments: --host or --client 'ip'");
+    let mode = match args.next() {
+        // The terminal has no start menu
+        None if terminal => return Ok(Command::Terminal(GameMode::Local)),
+        None => return Ok(Command::Play(None)),
+        Some(mode) => mode,
+    };
+    let mut value = |name: &str| args.next().ok_or_else(|| format!("Expected {} after {}", name, mode));
 
+    let game_mode = match mode.as_str() {
+        "--host" => GameMode::Host { address: value("address")? },
+        "--client" => GameMode::Join { address: value("address")? },
+        "--local" => GameMode::Local,
+        "--engine" => GameMode::Engine { command: value("command")? },
+        "--diagram" => {
+            let path = PathBuf::from(value("file")?);
+            return Ok(Command::Diagram { path, moves: args.collect() });
+        }
+        "--screenshot" => {
+            let path = PathBuf::from(value("file")?);
+            return Ok(Command::Screenshot { path, moves: args.collect() });
+        }
+        "--pgn" => {
+            let path = PathBuf::from(value("file")?);
+            return Ok(Command::Pgn { path, moves: args.collect() });
+        }
+        "--replay" => GameMode::Replay { path: PathBuf::from(value("file")?) },
+        _ => return Err(format!("Unknown command: {}", mode)),
+    };
+    if terminal {
+        Ok(Command::Terminal(game_mode))
+    } else {
+        Ok(Command::Play(Some(game_mode)))
+    }
+}
 
-    let name = match host_or_client.as_str() {
-        "--host" => {
-            "Chess - Host"
+/// Returns a local game with `moves`, in UCI notation, played from the starting position.
+fn replay(moves: &[String]) -> Result<ChessController, String> {
+    let mut chess_controller = ChessController::new(Opponent::Local);
+    for uci in moves {
+        let played = ChessMove::from_uci(uci).is_some_and(|chess_move| chess_controller.game.play(chess_move));
+        if !played {
+            return Err(format!("Illegal move: {}", uci));
         }
-        "--client" => {
-            "Chess - Client"
+    }
+    Ok(chess_controller)
+}
+
+/// Saves a diagram of the position after `moves`.
+fn save_diagram(settings: &ChessGraphicsSettings, path: &Path, moves: &[String]) -> Result<(), String> {
+    let chess_controller = replay(moves)?;
+    diagram::save(&chess_controller.diagram(), settings, path)
+        .map_err(|e| format!("Could not save {}: {}", path.display(), e))
+}
+
+/// Saves the window as it looks after `moves`, drawn on the CPU.
+fn save_screenshot(settings: ChessGraphicsSettings, path: &Path, moves: &[String]) -> Result<(), String> {
+    let chess_controller = replay(moves)?;
+    let chess_view: ChessGraphics<SoftwareTexture> = ChessGraphics::new(settings).map_err(|e| e.to_string())?;
+
+    let [width, height] = chess_view.settings.window_size();
+    let mut g = SoftwareGraphics::new(width.ceil() as u32, height.ceil() as u32);
+    g.draw(|c, g| {
+        clear(BACKGROUND_COLOR, g);
+        chess_view.draw(&chess_controller, &c, g);
+    });
+    g.into_image()
+        .save(path)
+        .map_err(|e| format!("Could not save {}: {}", path.display(), e))
+}
+
+/// Saves `moves` as a PGN game.
+fn save_pgn(path: &Path, moves: &[String]) -> Result<(), String> {
+    let chess_controller = replay(moves)?;
+    pgn::save(&chess_controller.game, path)
+        .map_err(|e| format!("Could not save {}: {}", path.display(), e))
+}
+
+/// Reads a PGN file, showing a chooser if it has several games.
+fn open_replay(path: &Path) -> Result<Screen, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let games = pgn::parse(&text).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    if games.len() == 1 {
+        Ok(Screen::Replay(ReplayViewer::new(games, 0)))
+    } else {
+        Ok(Screen::Chooser(GameChooser::new(games, 0)))
+    }
+}
+
+/// Exits once a command that doesn't open the window is done.
+fn exit_with(result: Result<(), String>) -> ! {
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Starts whatever the game mode needs from `start`, network games are connected on the waiting
+/// screen.
+fn start_game(mode: GameMode, start: Position) -> Result<Screen, String> {
+    let opponent = match mode {
+        GameMode::Host { address } => {
+            let pending = PendingConnection::host(&address)
+                .map_err(|e| format!("Could not host on {}: {}", address, e))?;
+            let description = format!("Waiting for an opponent to connect on {}", address);
+            return Ok(Screen::Waiting(WaitingScreen::new(pending, description, start)));
         }
-        // Only --host and --client are valid arguments
-        _ => panic!("Unknown command: {}", host_or_client),
+        GameMode::Join { address } => {
+            let pending = PendingConnection::join(&address);
+            let description = format!("Connecting to {}", address);
+            return Ok(Screen::Waiting(WaitingScreen::new(pending, description, start)));
+        }
+        GameMode::Editor { host_address, engine_command } => {
+            return Ok(Screen::Editor(BoardEditor::new(host_address, engine_command)));
+        }
+        GameMode::Replay { path } => return open_replay(&path),
+        GameMode::Local => Opponent::Local,
+        GameMode::Engine { command } => Opponent::Engine(
+            UciEngine::start(&command).map_err(|e| format!("Could not start engine '{}': {}", command, e))?,
+        ),
+    };
+    Ok(Screen::Game(ChessController::from_position(opponent, start)))
+}
+
+fn main() {
+    let opengl = OpenGL::V3_2;
+
+    let command = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+
+    let mut chess_view_settings = match std::env::var("CHESS_THEME").as_deref() {
+        Ok("high-contrast") => ChessGraphicsSettings::high_contrast(),
+        _ => ChessGraphicsSettings::new(),
+    };
+    // Sprites are embedded, but can be overridden with a directory of the same file names
+    chess_view_settings.sprites_dir = std::env::var_os("CHESS_SPRITES_DIR").map(PathBuf::from);
+
+    // Diagrams, screenshots, PGN files and terminal games don't open a window
+    let first_screen = match command {
+        Command::Play(Some(mode)) => start_game(mode, Position::initial()),
+        Command::Play(None) => Ok(Screen::Menu(StartMenu::new())),
+        Command::Diagram { path, moves } => exit_with(save_diagram(&chess_view_settings, &path, &moves)),
+        Command::Screenshot { path, moves } => exit_with(save_screenshot(chess_view_settings, &path, &moves)),
+        Command::Pgn { path, moves } => exit_with(save_pgn(&path, &moves)),
+        Command::Terminal(mode) => exit_with(terminal::play(mode)),
     };
 
-    let mut settings = WindowSettings::new(name, (600, 600))
-        .exit_on_esc(true)
+    let mut settings = WindowSettings::new("Chess", chess_view_settings.window_size())
+        .exit_on_esc(false)
         .graphics_api(opengl)
         .vsync(true);
 
@@ -50,22 +233,103 @@ fn main() {
     let mut events = Events::new(EventSettings::new().lazy(false));
     let mut gl = GlGraphics::new(opengl);
 
-    let mut chess_controller = ChessController::new();
-    let chess_view_settings = ChessGraphicsSettings::new();
-    let chess_view = ChessGraphics::new(chess_view_settings);
+    let chess_view: ChessGraphics<Texture> = match ChessGraphics::new(chess_view_settings) {
+        Ok(chess_view) => chess_view,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut screen = match first_screen {
+        Ok(screen) => screen,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
     while let Some(e) = events.next(&mut window) {
-        chess_controller.event(
-            chess_view.settings.offset,
-            chess_view.settings.size,
-            chess_view.settings.square_amount,
-            &e,
-        );
+        let mut next_screen = None;
+        match &mut screen {
+            Screen::Menu(menu) => {
+                if let Some(mode) = menu.event(&chess_view.settings, &e) {
+                    match start_game(mode, Position::initial()) {
+                        Ok(screen) => next_screen = Some(screen),
+                        Err(message) => menu.message = Some(message),
+                    }
+                }
+            }
+            Screen::Waiting(waiting) => {
+                next_screen = match waiting.event(&chess_view.settings, &e) {
+                    Some(WaitingOutcome::Connected(networking)) => {
+                        let start = waiting.start.clone();
+                        Some(Screen::Game(ChessController::from_position(Opponent::Network(networking), start)))
+                    }
+                    Some(WaitingOutcome::Failed(message)) => {
+                        let mut menu = StartMenu::new();
+                        menu.message = Some(message);
+                        Some(Screen::Menu(menu))
+                    }
+                    Some(WaitingOutcome::Cancelled) => Some(Screen::Menu(StartMenu::new())),
+                    None => None,
+                };
+            }
+            Screen::Editor(editor) => {
+                next_screen = match editor.event(&chess_view.settings, &e) {
+                    Some(EditorOutcome::Start(mode, start)) => match start_game(mode, start) {
+                        Ok(screen) => Some(screen),
+                        Err(message) => {
+                            editor.message = Some(message);
+                            None
+                        }
+                    },
+                    Some(EditorOutcome::Cancelled) => Some(Screen::Menu(StartMenu::new())),
+                    None => None,
+                };
+            }
+            Screen::Game(chess_controller) => chess_controller.event(&chess_view.settings, &e),
+            Screen::Chooser(chooser) => {
+                if let Some(index) = chooser.event(&chess_view.settings, &e) {
+                    next_screen = Some(Screen::Replay(ReplayViewer::new(std::mem::take(&mut chooser.games), index)));
+                }
+            }
+            Screen::Replay(viewer) => {
+                if viewer.event(&e) {
+                    let games = std::mem::take(&mut viewer.games);
+                    next_screen = Some(Screen::Chooser(GameChooser::new(games, viewer.index)));
+                }
+            }
+        }
+
+        // Escape cancels waiting for a connection, leaves the editor and goes back from a replay to
+        // the chooser, everywhere else it quits
+        if let Some(Button::Keyboard(Key::Escape)) = e.press_args() {
+            let goes_back = match &screen {
+                Screen::Waiting(_) | Screen::Editor(_) => true,
+                Screen::Replay(_) => next_screen.is_some(),
+                _ => false,
+            };
+            if !goes_back {
+                window.set_should_close(true);
+            }
+        }
+        if let Some(next_screen) = next_screen {
+            screen = next_screen;
+        }
+
         if let Some(args) = e.render_args() {
             gl.draw(args.viewport(), |c, g| {
-                clear([0.4, 0.4, 0.4, 1.0], g);
-                chess_view.draw(&chess_controller, &c, g);
+                clear(BACKGROUND_COLOR, g);
+                match &screen {
+                    Screen::Menu(menu) => chess_view.draw_menu(menu, &c, g),
+                    Screen::Waiting(waiting) => chess_view.draw_waiting(waiting, &c, g),
+                    Screen::Editor(editor) => chess_view.draw_editor(editor, &c, g),
+                    Screen::Game(chess_controller) => chess_view.draw(chess_controller, &c, g),
+                    Screen::Chooser(chooser) => chess_view.draw_chooser(chooser, &c, g),
+                    Screen::Replay(viewer) => chess_view.draw(&viewer.controller, &c, g),
+                }
             });
         }
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,8 @@
-use std::{boxed, io::{Read, Write}, net::{TcpListener, TcpStream}};
-use dynchess_lib::ChessBoard;
+use std::{io, net::{TcpListener, TcpStream}, thread};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use prost::Message;
-use crate::networking_protobuf;
+use crate::chess_rules::{ChessMove, PieceKind};
+use crate::transport::{TcpTransport, Transport, WebSocketTransport};
 use crate::networking_protobuf::{
     c2s_message,
     C2sConnectRequest,
@@ -9,234 +10,616 @@ use crate::networking_protobuf::{
     s2c_message,
     S2cConnectAck,
     S2cMessage,
-    Move
+    BoardState,
+    ChatMessage,
+    Move,
+    Piece,
+    TakebackRequest,
+    TakebackResponse
 };
 
+/// Version of the protocol this build speaks, sent in the connect handshake.
+///
+/// It goes up whenever a message changes in a way older builds can't read. Version 2 sends the
+/// length of each message over TCP.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version this build can play against. Version 1 builds don't send the length of
+/// TCP messages, and builds from before versions were sent send 0 and don't check the opponent's
+/// version either, so both are refused.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// Optional features this build supports, sent in the connect handshake. Features the opponent
+/// doesn't support are left out of the game.
+pub const CAPABILITIES: [&str; 4] = ["chat", "takeback", "start-position", "promotion"];
+
+/// Longest chat message in characters, longer ones are cut off before they are sent.
+pub const MAX_CHAT_LENGTH: usize = 200;
+
 #[derive(PartialEq, Debug)]
 pub enum State {
     Playing,
     WaitingForOpponent,
 }
 
+/// A message about taking back moves, which either side can send during the game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TakebackMessage {
+    /// Asks to take back every move after the first `ply` half moves.
+    Request { ply: u32 },
+    /// Answers a request.
+    Response { accepted: bool },
+}
+
 #[derive(PartialEq, Clone)]
 pub enum ConnectionType {
     Host(S2cMessage),
     Client(C2sMessage),
 }
 
+/// A network game being set up without blocking the event loop.
+pub enum PendingConnection {
+    /// Waiting for a client to connect to the non-blocking listener, over WebSocket if `websocket`.
+    Hosting { listener: TcpListener, websocket: bool },
+    /// Answering the WebSocket handshake of a client that connected, on a background thread.
+    Accepting(Receiver<io::Result<Box<dyn Transport>>>),
+    /// Connecting to the host on a background thread.
+    Joining(Receiver<io::Result<Box<dyn Transport>>>),
+}
+
+impl PendingConnection {
+    /// Starts listening for a client on `address`, which clients connect to over WebSocket if it
+    /// starts with `ws://`.
+    pub fn host(address: &str) -> io::Result<PendingConnection> {
+        let websocket = address.starts_with("ws://");
+        let listener = TcpListener::bind(address.trim_start_matches("ws://"))?;
+        listener.set_nonblocking(true)?;
+        Ok(PendingConnection::Hosting { listener, websocket })
+    }
+
+    /// Starts connecting to the host on `address`, over WebSocket if it starts with `ws://`.
+    pub fn join(address: &str) -> PendingConnection {
+        let (sender, receiver) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || {
+            let transport: io::Result<Box<dyn Transport>> = if address.starts_with("ws://") {
+                WebSocketTransport::connect(&address).map(|transport| Box::new(transport) as Box<dyn Transport>)
+            } else {
+                TcpStream::connect(address)
+                    .and_then(TcpTransport::new)
+                    .map(|transport| Box::new(transport) as Box<dyn Transport>)
+            };
+            // Nobody is listening if connecting was cancelled, so the result can be dropped
+            let _ = sender.send(transport);
+        });
+        PendingConnection::Joining(receiver)
+    }
+
+    /// Checks whether the connection is set up, returns `None` while still waiting.
+    pub fn poll(&mut self) -> Option<io::Result<Networking>> {
+        match self {
+            PendingConnection::Hosting { listener, websocket } => match listener.accept() {
+                // The handshake blocks until the client answers, so it doesn't run on this thread
+                Ok((stream, _)) if *websocket => {
+                    let (sender, receiver) = mpsc::channel();
+                    thread::spawn(move || {
+                        let transport = WebSocketTransport::accept(stream)
+                            .map(|transport| Box::new(transport) as Box<dyn Transport>);
+                        // Nobody is listening if hosting was cancelled, so the result can be dropped
+                        let _ = sender.send(transport);
+                    });
+                    *self = PendingConnection::Accepting(receiver);
+                    None
+                }
+                Ok((stream, _)) => {
+                    let transport = TcpTransport::new(stream).map(|transport| Box::new(transport) as Box<dyn Transport>);
+                    // The listener stays open on the host for spectators, who connect over TCP
+                    let listener = listener.try_clone().ok();
+                    Some(transport.map(|transport| Networking::host(transport, listener)))
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => None,
+                Err(e) => Some(Err(e)),
+            },
+            // Spectators can't join WebSocket games
+            PendingConnection::Accepting(receiver) => {
+                received_transport(receiver).map(|transport| transport.map(|transport| Networking::host(transport, None)))
+            }
+            PendingConnection::Joining(receiver) => {
+                received_transport(receiver).map(|transport| transport.map(Networking::join))
+            }
+        }
+    }
+}
+
+/// Returns the transport a background thread set up, or `None` while it is still working.
+fn received_transport(receiver: &Receiver<io::Result<Box<dyn Transport>>>) -> Option<io::Result<Box<dyn Transport>>> {
+    match receiver.try_recv() {
+        Ok(transport) => Some(transport),
+        Err(TryRecvError::Empty) => None,
+        Err(TryRecvError::Disconnected) => Some(Err(io::Error::other("Connecting stopped unexpectedly"))),
+    }
+}
+
 pub struct Networking {
-    // Logic
-    // pub from: u8,
-    // pub to: u8,
     pub state: State,
 
     // Networking
-    pub socket: TcpStream,
+    /// Carries the messages to and from the opponent.
+    pub transport: Box<dyn Transport>,
 
     pub connection: ConnectionType,
     pub game_id: u64,
+    /// FEN of the position the game starts from, if it isn't the initial one.
+    ///
+    /// The host sends it with the connect acknowledgement, and it is set on the client when that
+    /// arrives.
+    pub starting_position: Option<String>,
+    /// Takeback message received from the opponent, until the game picks it up.
+    pub takeback: Option<TakebackMessage>,
+    /// Chat messages received from the opponent and spectators, until the game picks them up.
+    pub chat: Vec<ChatMessage>,
+    /// Listener the host accepts spectators on once the opponent has joined.
+    listener: Option<TcpListener>,
+    /// Connections of the host's spectators, who are sent every chat message.
+    spectators: Vec<TcpTransport>,
+    /// Optional features the opponent supports, known once the handshake has finished.
+    pub peer_capabilities: Vec<String>,
+    /// Why the game can't be played, if the handshake failed, e.g. the opponent's build speaks an
+    /// incompatible protocol version.
+    pub rejection: Option<String>,
 }
 
 impl Networking {
-    pub(crate) fn new() -> Networking {
+    /// Creates the host side of a game played over `transport`, accepting spectators on
+    /// `listener`.
+    pub fn host(transport: Box<dyn Transport>, listener: Option<TcpListener>) -> Networking {
         let game_id = 1;
-        // A stream and a boolean indicating whether or not the program is a host or a client
-        let (stream, is_client, mut connection_type) = {
-            let mut args = std::env::args();
-            // Skip path to program
-            args.next();
-
-            // Get first argument after path to program
-            let host_or_client = args
-                .next()
-                .expect("Expected arguments: --host or --client 'ip'");
-
-            match host_or_client.as_str() {
-                // If the program is running as host we listen on port 8080 until we get a
-                // connection then we return the stream.
-                "--host" => {
-                    let ip = args.next().expect("Expected ip address after --client");
-                    let listener = TcpListener::bind(ip).unwrap();
-                    (listener.incoming().next().unwrap().unwrap(), false, ConnectionType::Host(
-                        S2cMessage {
-                            msg: Some(s2c_message::Msg::ConnectAck(S2cConnectAck {
-                                success: false,
-                                game_id: Some(game_id),
-                                starting_position: None,
-                                client_is_white: None
-                            }))
-                        })
-                    )
-                }
-                // If the program is running as a client we connect to the specified IP address and
-                // return the stream.
-                "--client" => {
-                    let ip = args.next().expect("Expected ip address after --client");
-                    let stream = TcpStream::connect(ip).expect("Failed to connect to host");
-                    (stream, true, ConnectionType::Client(
-                        C2sMessage{
-                            msg: Some(c2s_message::Msg::ConnectRequest(C2sConnectRequest{
-                                game_id,
-                                spectate: false
-                            }))
-                        })
-                    )
-                }
-                // Only --host and --client are valid arguments
-                _ => panic!("Unknown command: {}", host_or_client),
-            }
-        };
+        let mut networking = Self::from_transport(transport, false, ConnectionType::Host(
+            S2cMessage {
+                msg: Some(s2c_message::Msg::ConnectAck(S2cConnectAck {
+                    success: false,
+                    game_id: Some(game_id),
+                    starting_position: None,
+                    client_is_white: None,
+                    protocol_version: PROTOCOL_VERSION,
+                    capabilities: Vec::new(),
+                    error: None
+                }))
+            }),
+            game_id,
+        );
+        networking.listener = listener;
+        networking
+    }
 
-        // Set TcpStream to non blocking so that we can do networking in the update thread
-        stream
-            .set_nonblocking(true)
-            .expect("Failed to set stream to non blocking");
+    /// Creates the client side of a game played over `transport`, and sends the host the connect
+    /// request, once.
+    pub fn join(transport: Box<dyn Transport>) -> Networking {
+        let game_id = 1;
+        let mut networking = Self::from_transport(transport, true, ConnectionType::Client(
+            C2sMessage{
+                msg: Some(c2s_message::Msg::ConnectRequest(C2sConnectRequest{
+                    game_id,
+                    spectate: false,
+                    protocol_version: PROTOCOL_VERSION,
+                    capabilities: CAPABILITIES.iter().map(|capability| capability.to_string()).collect()
+                }))
+            }),
+            game_id,
+        );
+        if let Err(e) = networking.send_packet(None) {
+            networking.rejection = Some(format!("Lost the connection: {}", e));
+        }
+        networking
+    }
 
+    fn from_transport(
+        transport: Box<dyn Transport>,
+        is_client: bool,
+        connection_type: ConnectionType,
+        game_id: u64,
+    ) -> Networking {
         Networking {
-            // from: if client { 0 } else { 63 },
-            // to: if client { 63 } else { 0 },
             // Host starts playing and the client waits
             state: if is_client {
                 State::WaitingForOpponent
             } else {
                 State::Playing
             },
-            socket: stream,
+            transport,
             connection: connection_type,
             game_id,
+            starting_position: None,
+            takeback: None,
+            chat: Vec::new(),
+            listener: None,
+            spectators: Vec::new(),
+            peer_capabilities: Vec::new(),
+            rejection: None,
         }
     }
 
-    /// Checks if a move packet is available in returns the new positions otherwise it returns none
-    pub fn receive_packet(&mut self) -> Option<[u8; 2]> {
-        // println!("Received packet");
-        let mut buf = [0u8; 512];
-        let mut coords = None;
-        let (packet_length, packet_ok) = match self.socket.read(&mut buf) {
-            Ok(length) => (length, true),
-            Err(e) => (0, false)
+    /// Returns whether this side is hosting the game.
+    pub fn is_host(&self) -> bool {
+        matches!(self.connection, ConnectionType::Host(_))
+    }
+
+    /// Returns whether the connect handshake has finished.
+    pub fn is_connected(&self) -> bool {
+        match &self.connection {
+            ConnectionType::Host(host) => host.msg.is_none(),
+            ConnectionType::Client(client) => client.msg.is_none(),
+        }
+    }
+
+    /// Returns whether both this build and the opponent's support an optional feature from
+    /// `CAPABILITIES`.
+    pub fn supports(&self, capability: &str) -> bool {
+        CAPABILITIES.contains(&capability) && self.peer_capabilities.iter().any(|peer| peer == capability)
+    }
+
+    /// Describes the opponent, e.g. their address.
+    pub fn peer(&self) -> Option<String> {
+        self.transport.peer()
+    }
+
+    /// Checks if a move packet is available and returns the move, otherwise it returns none
+    pub fn receive_packet(&mut self) -> Option<ChessMove> {
+        let mut received_move = None;
+        let buf = match self.transport.poll() {
+            Ok(Some(buf)) => buf,
+            // Nothing has arrived, or the opponent closed the connection
+            Ok(None) | Err(_) => return None,
         };
 
-        if packet_ok {
-            // println!("Packet is ok");
-            match &self.connection {
-                ConnectionType::Host(host) => {
-                    let packet_decoded = C2sMessage::decode(&buf[0..packet_length]);
-                    match packet_decoded.clone().unwrap().msg.unwrap() {
-                        c2s_message::Msg::Move(move_packet) => {
-                            self.connection = ConnectionType::Host(
-                                S2cMessage{ msg: None }
-                            );
-                            coords = Some([
-                                move_packet.from_square as u8,
-                                move_packet.to_square as u8
-                            ]);
+        match &self.connection {
+            ConnectionType::Host(_) => {
+                // Messages that can't be read, or are empty, are left out
+                let msg = match C2sMessage::decode(&buf[..]) {
+                    Ok(C2sMessage { msg: Some(msg) }) => msg,
+                    _ => return None,
+                };
+                match msg {
+                    // Moves before the handshake would skip the version and capability checks
+                    c2s_message::Msg::Move(_) if !self.is_connected() => {}
+                    c2s_message::Msg::Move(move_packet) => {
+                        // Moves off the board are left out
+                        if let Some(chess_move) = chess_move(&move_packet) {
+                            received_move = Some(chess_move);
                             self.state = State::Playing;
-                            // println!("changed state, returned new coords");
                         }
-                        c2s_message::Msg::ConnectRequest(_) => {
-                            self.connection = ConnectionType::Host(
-                                S2cMessage {
-                                    msg: Some(s2c_message::Msg::ConnectAck(S2cConnectAck {
-                                        success: true,
-                                        game_id: Some(self.game_id),
-                                        starting_position: None,
-                                        client_is_white: None
-                                    }))
-                                }
-                            );
-                            self.send_packet(None);
-                            self.connection = ConnectionType::Host(
-                                S2cMessage{ msg: None }
-                            );
+                    }
+                    // A request repeated after the handshake is left out
+                    c2s_message::Msg::ConnectRequest(_) if self.is_connected() => {}
+                    c2s_message::Msg::ConnectRequest(request) => {
+                        let refusal = check_protocol_version(request.protocol_version).err().or_else(|| {
+                            let start_position = request.capabilities.iter().any(|peer| peer == "start-position");
+                            (self.starting_position.is_some() && !start_position)
+                                .then(|| String::from("The client's build can't start from a set-up position"))
+                        });
+                        self.connection = ConnectionType::Host(
+                            S2cMessage {
+                                msg: Some(s2c_message::Msg::ConnectAck(S2cConnectAck {
+                                    success: refusal.is_none(),
+                                    game_id: Some(self.game_id),
+                                    starting_position: self.starting_position
+                                        .clone()
+                                        .map(|fen_string| BoardState { fen_string }),
+                                    client_is_white: None,
+                                    protocol_version: PROTOCOL_VERSION,
+                                    capabilities: CAPABILITIES.iter().map(|capability| capability.to_string()).collect(),
+                                    error: refusal.clone()
+                                }))
+                            }
+                        );
+                        if let Err(e) = self.send_packet(None) {
+                            self.rejection = Some(format!("Lost the connection: {}", e));
+                            return None;
+                        }
+                        match refusal {
+                            Some(refusal) => self.rejection = Some(format!("Refused the opponent: {}", refusal)),
+                            None => {
+                                self.peer_capabilities = request.capabilities;
+                                self.connection = ConnectionType::Host(
+                                    S2cMessage{ msg: None }
+                                );
+                            }
                         }
                     }
-                    // println!("{:?}", packet_decoded);
+                    c2s_message::Msg::TakebackRequest(request) => {
+                        self.takeback = Some(TakebackMessage::Request { ply: request.ply });
+                    }
+                    c2s_message::Msg::TakebackResponse(response) => {
+                        self.takeback = Some(TakebackMessage::Response { accepted: response.accepted });
+                    }
+                    c2s_message::Msg::Chat(chat) => {
+                        self.relay_chat(&chat, None);
+                        self.chat.push(chat);
+                    }
                 }
-                ConnectionType::Client(client) => {
-                    let packet_decoded = S2cMessage::decode(&buf[0..packet_length]);
-
-                    match packet_decoded.clone().unwrap().msg.unwrap() {
-                        s2c_message::Msg::Move(move_packet) => {
-                            coords = Some([
-                                move_packet.from_square as u8,
-                                move_packet.to_square as u8
-                            ]);
+            }
+            ConnectionType::Client(_) => {
+                // Messages that can't be read, or are empty, are left out
+                let msg = match S2cMessage::decode(&buf[..]) {
+                    Ok(S2cMessage { msg: Some(msg) }) => msg,
+                    _ => return None,
+                };
+
+                match msg {
+                    s2c_message::Msg::Move(move_packet) => {
+                        // Moves off the board are left out
+                        if let Some(chess_move) = chess_move(&move_packet) {
+                            received_move = Some(chess_move);
                             self.state = State::Playing;
-                            // println!("changed state, returned new coords");
                         }
-                        s2c_message::Msg::ConnectAck(con_ack) => {
-                            if con_ack.success {
-                                // println!("successful connection");
+                    }
+                    // Only the first acknowledgement counts, a repeated one would restart the game
+                    s2c_message::Msg::ConnectAck(_) if self.is_connected() => {}
+                    s2c_message::Msg::ConnectAck(con_ack) => {
+                        match check_protocol_version(con_ack.protocol_version) {
+                            _ if !con_ack.success => {
+                                let error = con_ack.error.unwrap_or_else(|| String::from("no reason given"));
+                                self.rejection = Some(format!("The host refused the connection: {}", error));
+                            }
+                            Err(e) => {
+                                self.rejection = Some(format!("Can't play the host: {}", e));
+                                // Closing the connection tells the host too
+                                self.transport.close();
+                            }
+                            Ok(()) => {
                                 self.connection = ConnectionType::Client(
                                     C2sMessage{ msg: None }
                                 );
+                                self.starting_position = con_ack.starting_position
+                                    .map(|board_state| board_state.fen_string);
+                                self.peer_capabilities = con_ack.capabilities;
                             }
                         }
-                        s2c_message::Msg::MoveAck(_) => {}
                     }
-
-                    // println!("{:?}", packet_decoded);
+                    s2c_message::Msg::MoveAck(_) => {}
+                    s2c_message::Msg::TakebackRequest(request) => {
+                        self.takeback = Some(TakebackMessage::Request { ply: request.ply });
+                    }
+                    s2c_message::Msg::TakebackResponse(response) => {
+                        self.takeback = Some(TakebackMessage::Response { accepted: response.accepted });
+                    }
+                    s2c_message::Msg::Chat(chat) => self.chat.push(chat),
                 }
-            };
-        }
 
-        coords
+            }
+        };
+
+        received_move
     }
 
-    /// Sends a move packet of the current position and sets the state to waiting
-    pub fn send_packet(&mut self, coords: Option<[u8; 2]>) {
-        let mut buf = match self.connection.clone() {
+    /// Sends a move packet with `chess_move`, including its promotion, or the pending handshake
+    /// message if there is no move.
+    pub fn send_packet(&mut self, chess_move: Option<ChessMove>) -> io::Result<()> {
+        let buf = match self.connection.clone() {
             ConnectionType::Host(host) => {
                 let mut new_host= host;
-                if let Some(coords_vec) = coords {
+                if let Some(chess_move) = chess_move {
                     new_host = S2cMessage {
-                        msg: Some(s2c_message::Msg::Move(Move {
-                            from_square: coords_vec[0] as u32,
-                            to_square: coords_vec[1] as u32,
-                            promotion: None
-                        }))
+                        msg: Some(s2c_message::Msg::Move(move_message(chess_move)))
                     };
-                    // println!("changed cords to send them {:?}", coords_vec);
                 }
 
                 prost::Message::encode_to_vec(&new_host)
             }
             ConnectionType::Client(client) => {
                 let mut new_client= client;
-                if let Some(coords_vec) = coords {
+                if let Some(chess_move) = chess_move {
                     new_client = C2sMessage {
-                        msg: Some(c2s_message::Msg::Move(Move {
-                            from_square: coords_vec[0] as u32,
-                            to_square: coords_vec[1] as u32,
-                            promotion: None
-                        }))
+                        msg: Some(c2s_message::Msg::Move(move_message(chess_move)))
                     };
-                    // println!("changed cords to send them {:?}", coords_vec);
                 }
 
                 prost::Message::encode_to_vec(&new_client)
             }
         };
 
-        self.socket
-            .write(&buf)
-            .expect("Failed to send move packet");
-        // self.state = State::WaitingForOpponent;
-        // println!("Packet send: {:?}", buf);
+        self.transport.send(&buf)
+    }
+
+    /// Sends a takeback request or the answer to one.
+    pub fn send_takeback(&mut self, takeback: TakebackMessage) -> io::Result<()> {
+        let buf = match self.connection {
+            ConnectionType::Host(_) => {
+                let msg = match takeback {
+                    TakebackMessage::Request { ply } => {
+                        s2c_message::Msg::TakebackRequest(TakebackRequest { ply })
+                    }
+                    TakebackMessage::Response { accepted } => {
+                        s2c_message::Msg::TakebackResponse(TakebackResponse { accepted })
+                    }
+                };
+                prost::Message::encode_to_vec(&S2cMessage { msg: Some(msg) })
+            }
+            ConnectionType::Client(_) => {
+                let msg = match takeback {
+                    TakebackMessage::Request { ply } => {
+                        c2s_message::Msg::TakebackRequest(TakebackRequest { ply })
+                    }
+                    TakebackMessage::Response { accepted } => {
+                        c2s_message::Msg::TakebackResponse(TakebackResponse { accepted })
+                    }
+                };
+                prost::Message::encode_to_vec(&C2sMessage { msg: Some(msg) })
+            }
+        };
+
+        self.transport.send(&buf)
+    }
+
+    /// Sends a chat message to the opponent, and to the spectators when hosting.
+    pub fn send_chat(&mut self, sender: &str, text: &str) -> io::Result<()> {
+        let chat = capped_chat(ChatMessage { sender: sender.to_string(), text: text.to_string() });
+        let buf = match self.connection {
+            ConnectionType::Host(_) => {
+                self.relay_chat(&chat, None);
+                prost::Message::encode_to_vec(&S2cMessage { msg: Some(s2c_message::Msg::Chat(chat)) })
+            }
+            ConnectionType::Client(_) => {
+                prost::Message::encode_to_vec(&C2sMessage { msg: Some(c2s_message::Msg::Chat(chat)) })
+            }
+        };
+
+        self.transport.send(&buf)
+    }
+
+    /// Returns how many spectators are watching the host's game.
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// Accepts spectators and reads their messages, acknowledging their connect requests with
+    /// `fen`, the current position, and passing their chat messages on. Never blocks.
+    ///
+    /// Only the host has spectators, this does nothing on the client. Spectators that leave or
+    /// can't be written to are dropped, the error is only for passing chat on to the opponent.
+    pub fn serve_spectators(&mut self, fen: &str) -> io::Result<()> {
+        if let Some(listener) = &self.listener {
+            while let Ok((stream, _)) = listener.accept() {
+                if let Ok(spectator) = TcpTransport::new(stream) {
+                    self.spectators.push(spectator);
+                }
+            }
+        }
+
+        let mut index = 0;
+        while index < self.spectators.len() {
+            let message = match self.spectators[index].poll() {
+                Ok(Some(buf)) => match C2sMessage::decode(&buf[..]) {
+                    Ok(message) => Some(message.msg),
+                    Err(_) => Some(None),
+                },
+                Ok(None) => Some(None),
+                // The spectator left
+                Err(_) => None,
+            };
+
+            match message {
+                None => {
+                    self.spectators.remove(index);
+                    continue;
+                }
+                Some(Some(c2s_message::Msg::ConnectRequest(_))) => {
+                    let ack = S2cMessage {
+                        msg: Some(s2c_message::Msg::ConnectAck(S2cConnectAck {
+                            success: true,
+                            game_id: Some(self.game_id),
+                            starting_position: Some(BoardState { fen_string: fen.to_string() }),
+                            client_is_white: None,
+                            protocol_version: PROTOCOL_VERSION,
+                            capabilities: CAPABILITIES.iter().map(|capability| capability.to_string()).collect(),
+                            error: None
+                        }))
+                    };
+                    if self.spectators[index].send(&prost::Message::encode_to_vec(&ack)).is_err() {
+                        self.spectators.remove(index);
+                        continue;
+                    }
+                }
+                Some(Some(c2s_message::Msg::Chat(chat))) => {
+                    let chat = capped_chat(chat);
+                    let buf = prost::Message::encode_to_vec(&S2cMessage {
+                        msg: Some(s2c_message::Msg::Chat(chat.clone()))
+                    });
+                    self.relay_chat(&chat, Some(index));
+                    self.chat.push(chat);
+                    self.transport.send(&buf)?;
+                }
+                // Spectators only watch, so anything else is ignored
+                Some(_) => {}
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+
+    /// Sends a chat message to every spectator but the one at `except`, who wrote it.
+    fn relay_chat(&mut self, chat: &ChatMessage, except: Option<usize>) {
+        let buf = prost::Message::encode_to_vec(&S2cMessage {
+            msg: Some(s2c_message::Msg::Chat(chat.clone()))
+        });
+        for (index, spectator) in self.spectators.iter_mut().enumerate() {
+            // A spectator that can't be written to is closed, and dropped when it is read next
+            if Some(index) != except && spectator.send(&buf).is_err() {
+                spectator.close();
+            }
+        }
     }
 
     pub fn update(&mut self) {
         match self.state {
             State::Playing => {}
             State::WaitingForOpponent => {
-                // If we received at move packet we first set the enemy pos to the received
-                // position and then set the state to playing
-                if let Some(pos) = self.receive_packet() {
+                // A move from the opponent makes it this side's turn
+                if self.receive_packet().is_some() {
                     self.state = State::Playing;
-                    // self.enemy_pos = pos;
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Cuts the sender and text of a chat message off at `MAX_CHAT_LENGTH` characters.
+fn capped_chat(chat: ChatMessage) -> ChatMessage {
+    ChatMessage {
+        sender: chat.sender.chars().take(MAX_CHAT_LENGTH).collect(),
+        text: chat.text.chars().take(MAX_CHAT_LENGTH).collect(),
+    }
+}
+
+/// Returns the message for a move, with the piece a pawn promotes to.
+fn move_message(chess_move: ChessMove) -> Move {
+    let promotion = chess_move.promotion.map(|kind| match kind {
+        PieceKind::Pawn => Piece::Pawn,
+        PieceKind::Knight => Piece::Knight,
+        PieceKind::Bishop => Piece::Bishop,
+        PieceKind::Rook => Piece::Rook,
+        PieceKind::Queen => Piece::Queen,
+        PieceKind::King => Piece::King,
+    });
+    Move {
+        from_square: chess_move.from as u32,
+        to_square: chess_move.to as u32,
+        promotion: promotion.map(|piece| piece as i32),
+    }
+}
+
+/// Returns the move in a move message, leaving out a promotion piece that isn't known, or `None`
+/// if either square is off the board.
+fn chess_move(message: &Move) -> Option<ChessMove> {
+    if message.from_square >= 64 || message.to_square >= 64 {
+        return None;
+    }
+    let promotion = message.promotion.and_then(Piece::from_i32).map(|piece| match piece {
+        Piece::Pawn => PieceKind::Pawn,
+        Piece::Knight => PieceKind::Knight,
+        Piece::Bishop => PieceKind::Bishop,
+        Piece::Rook => PieceKind::Rook,
+        Piece::Queen => PieceKind::Queen,
+        Piece::King => PieceKind::King,
+    });
+    Some(ChessMove {
+        from: message.from_square as u8,
+        to: message.to_square as u8,
+        promotion,
+    })
+}
+
+/// Returns why the opponent's build can't be played against, if it speaks `version` of the
+/// protocol.
+///
+/// Both sides check, so the newer build decides whether it still understands the older one.
+pub fn check_protocol_version(version: u32) -> Result<(), String> {
+    if version < MIN_PROTOCOL_VERSION {
+        Err(format!(
+            "the opponent speaks protocol version {}, this build needs at least version {}, update the older build",
+            version, MIN_PROTOCOL_VERSION
+        ))
+    } else {
+        Ok(())
+    }
+}
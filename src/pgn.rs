@@ -0,0 +1,551 @@
+//! Games written in and read from Portable Game Notation, for other chess programs and for
+//! reviewing games.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::chess_rules::{ChessMove, GameResult, Position, Side};
+use crate::game::Game;
+use crate::game_tree::GameTree;
+
+/// Lines of movetext are wrapped before this length, as the PGN export format asks.
+const LINE_LENGTH: usize = 79;
+
+/// Directory network games are saved to when `CHESS_GAMES_DIR` isn't set.
+const DEFAULT_GAMES_DIR: &str = "games";
+
+/// Tags describing a game, written before its moves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameMetadata {
+    pub event: String,
+    /// Where the game is played, e.g. the address of a network opponent.
+    pub site: String,
+    /// Day the game started, as `YYYY.MM.DD` in UTC.
+    pub date: String,
+    pub white: String,
+    pub black: String,
+    /// Time control in PGN notation, `-` when the game isn't timed.
+    pub time_control: String,
+}
+
+impl GameMetadata {
+    /// Creates metadata for a game starting now, with the players' names unknown.
+    pub fn new(event: &str) -> GameMetadata {
+        let (year, month, day) = civil_date(seconds_since_epoch());
+        GameMetadata {
+            event: event.to_string(),
+            site: String::from("?"),
+            date: format!("{:04}.{:02}.{:02}", year, month, day),
+            white: String::from("?"),
+            black: String::from("?"),
+            time_control: String::from("-"),
+        }
+    }
+}
+
+/// Returns the name of this player, from `CHESS_PLAYER_NAME`.
+pub fn player_name() -> String {
+    std::env::var("CHESS_PLAYER_NAME").unwrap_or_else(|_| String::from("?"))
+}
+
+/// Returns the result tag of a game ending in `position`, `*` while it goes on.
+pub fn result_tag(position: &Position) -> &'static str {
+    match position.result() {
+        Some(GameResult::Checkmate { winner: Side::White }) => "1-0",
+        Some(GameResult::Checkmate { winner: Side::Black }) => "0-1",
+        Some(_) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+/// Writes `game` as PGN, with how long each move took in `%emt` comments.
+pub fn write(game: &Game) -> String {
+    let metadata = &game.metadata;
+    let result = result_tag(&game.position);
+
+    let mut tags = vec![
+        ("Event", metadata.event.as_str()),
+        ("Site", metadata.site.as_str()),
+        ("Date", metadata.date.as_str()),
+        ("Round", "-"),
+        ("White", metadata.white.as_str()),
+        ("Black", metadata.black.as_str()),
+        ("Result", result),
+        ("TimeControl", metadata.time_control.as_str()),
+    ];
+    let fen = game.start.to_fen();
+    if game.start != Position::initial() {
+        tags.push(("SetUp", "1"));
+        tags.push(("FEN", &fen));
+    }
+
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(pgn, "[{} \"{}\"]", name, value);
+    }
+    pgn.push('\n');
+
+    // Move numbers count from the starting position, which may have Black to move. Black's moves
+    // follow a comment, so they get a move number too
+    let mut tokens = Vec::new();
+    let mut number = game.start.fullmove_number;
+    let mut side = game.start.side_to_move;
+    for played_move in &game.history {
+        match side {
+            Side::White => tokens.push(format!("{}.", number)),
+            Side::Black => tokens.push(format!("{}...", number)),
+        }
+        tokens.push(played_move.san.clone());
+        tokens.push(format!("{{[%emt {}]}}", format_duration(played_move.elapsed)));
+
+        if side == Side::Black {
+            number += 1;
+        }
+        side = side.opponent();
+    }
+    tokens.push(result.to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
+
+/// Saves `game` as PGN to `path`, creating its directory if needed.
+pub fn save(game: &Game, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, write(game))
+}
+
+/// Returns the directory games are saved to, `CHESS_GAMES_DIR` or `games` in the working
+/// directory.
+pub fn games_dir() -> PathBuf {
+    std::env::var_os("CHESS_GAMES_DIR").map_or_else(|| PathBuf::from(DEFAULT_GAMES_DIR), PathBuf::from)
+}
+
+/// Returns a new file in the games directory for a game starting now, named after the time and
+/// `side`, e.g. `host`.
+pub fn autosave_path(side: &str) -> PathBuf {
+    let dir = games_dir();
+    let seconds = seconds_since_epoch();
+    let (year, month, day) = civil_date(seconds);
+    let time = seconds % 86_400;
+    dir.join(format!(
+        "{:04}-{:02}-{:02}-{:02}{:02}{:02}-{}.pgn",
+        year, month, day, time / 3600, time / 60 % 60, time % 60, side
+    ))
+}
+
+/// Formats a duration as `h:mm:ss`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn seconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Returns the year, month and day in UTC of a time in seconds since the Unix epoch.
+fn civil_date(seconds: u64) -> (u64, u64, u64) {
+    // Days are counted in 400 year eras starting on March 1st, so leap days end each year
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A move read from PGN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnMove {
+    pub chess_move: ChessMove,
+    /// The move in SAN, as this program writes it.
+    pub san: String,
+    /// Comments after the move, joined by spaces.
+    pub comment: Option<String>,
+}
+
+/// A game read from PGN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnGame {
+    /// Tags in the order they were read.
+    pub tags: Vec<(String, String)>,
+    /// Position the moves start from, from the `FEN` tag if there is one.
+    pub start: Position,
+    /// Moves of the main line.
+    pub moves: Vec<PgnMove>,
+    /// Every line of the game, the main line first and then the variations in the file.
+    pub tree: GameTree,
+}
+
+impl PgnGame {
+    /// Returns the value of a tag, if the game has it.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Describes the game in one line, e.g. to choose it from a file with several games.
+    pub fn description(&self) -> String {
+        let tag = |name: &str| self.tag(name).unwrap_or("?");
+        format!(
+            "{} - {}  {}  {}  {}",
+            tag("White"),
+            tag("Black"),
+            tag("Event"),
+            tag("Date"),
+            tag("Result")
+        )
+    }
+}
+
+/// A game being read, until its result or the next game's tags.
+struct GameReader {
+    tags: Vec<(String, String)>,
+    /// The position after the moves so far, set up once the movetext starts.
+    position: Option<Position>,
+    start: Position,
+    /// Moves of the main line.
+    moves: Vec<PgnMove>,
+    tree: GameTree,
+    /// Nodes of the moves of the line being read, the main line or a variation.
+    line: Vec<usize>,
+    /// Positions before each move of `line`, where a variation replacing it starts.
+    positions: Vec<Position>,
+    /// Lines the variations being read branch off, to go back to at their ends.
+    variations: Vec<(Vec<usize>, Vec<Position>, Option<Position>)>,
+}
+
+impl GameReader {
+    fn new() -> GameReader {
+        GameReader {
+            tags: Vec::new(),
+            position: None,
+            start: Position::initial(),
+            moves: Vec::new(),
+            tree: GameTree::new(),
+            line: Vec::new(),
+            positions: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+
+    /// Returns whether nothing of the game has been read yet.
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.position.is_none()
+    }
+
+    /// Returns the position to play the next move in, setting it up from the tags at the first
+    /// move.
+    fn position(&mut self) -> Result<&mut Position, String> {
+        if self.position.is_none() {
+            if let Some((_, fen)) = self.tags.iter().find(|(tag, _)| tag == "FEN") {
+                self.start = Position::from_fen(fen)?;
+            }
+            self.position = Some(self.start.clone());
+        }
+        Ok(self.position.as_mut().unwrap())
+    }
+
+    fn add_comment(&mut self, comment: &str) {
+        let comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+        // Comments before the first move and inside variations are dropped
+        match self.moves.last_mut() {
+            Some(last) if self.variations.is_empty() && !comment.is_empty() => {
+                last.comment = Some(match last.comment.take() {
+                    Some(earlier) => format!("{} {}", earlier, comment),
+                    None => comment,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn add_move(&mut self, text: &str) -> Result<(), String> {
+        let position = self.position()?;
+        let chess_move = position.parse_move(text).ok_or_else(|| format!("Illegal move: {}", text))?;
+        let san = position.san(chess_move);
+        let before = position.clone();
+        position.make_move(chess_move);
+
+        let node = self.tree.add(self.line.last().copied(), chess_move, &san);
+        self.line.push(node);
+        self.positions.push(before);
+        if self.variations.is_empty() {
+            self.moves.push(PgnMove { chess_move, san, comment: None });
+        }
+        Ok(())
+    }
+
+    /// Starts a variation, which replaces the last move read.
+    fn start_variation(&mut self) -> Result<(), String> {
+        let before = match self.positions.last() {
+            Some(before) => before.clone(),
+            None => return Err(String::from("Variation before the first move")),
+        };
+        let position = self.position.replace(before);
+        self.variations.push((self.line.clone(), self.positions.clone(), position));
+        self.line.pop();
+        self.positions.pop();
+        Ok(())
+    }
+
+    /// Ends a variation, going back to the line it branched off.
+    fn end_variation(&mut self) {
+        if let Some((line, positions, position)) = self.variations.pop() {
+            self.line = line;
+            self.positions = positions;
+            self.position = position;
+        }
+    }
+
+    fn finish(mut self) -> Result<PgnGame, String> {
+        // Sets up the start of games without moves
+        self.position()?;
+        Ok(PgnGame {
+            tags: self.tags,
+            start: self.start,
+            moves: self.moves,
+            tree: self.tree,
+        })
+    }
+}
+
+/// Reads every game in a PGN file.
+///
+/// Comments are kept with the move they follow, while numeric annotation glyphs are skipped.
+/// Variations, nested or not, are read into the game's tree without their comments. Moves may be
+/// in SAN or UCI notation.
+pub fn parse(text: &str) -> Result<Vec<PgnGame>, String> {
+    let mut games = Vec::new();
+    let mut game = GameReader::new();
+    let mut chars = text.chars().peekable();
+    let mut at_line_start = true;
+
+    let game_error = |games: &Vec<PgnGame>, e: String| format!("Game {}: {}", games.len() + 1, e);
+
+    while let Some(c) = chars.next() {
+        let line_start = at_line_start;
+        at_line_start = c == '\n';
+        match c {
+            _ if c.is_whitespace() => {}
+            // Lines starting with % are escaped for other programs
+            '%' if line_start => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        at_line_start = true;
+                        break;
+                    }
+                }
+            }
+            '[' => {
+                // Tags after movetext start the next game
+                if game.position.is_some() {
+                    let finished = std::mem::replace(&mut game, GameReader::new());
+                    games.push(finished.finish().map_err(|e| game_error(&games, e))?);
+                }
+                game.tags.push(parse_tag(&mut chars).map_err(|e| game_error(&games, e))?);
+            }
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                game.add_comment(&comment);
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                at_line_start = true;
+                game.add_comment(&comment);
+            }
+            '(' => game.start_variation().map_err(|e| game_error(&games, e))?,
+            ')' => game.end_variation(),
+            '$' => {
+                while chars.next_if(|c| c.is_ascii_digit()).is_some() {}
+            }
+            _ => {
+                let mut symbol = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"[]{}();$".contains(*c)) {
+                    symbol.push(c);
+                }
+
+                if ["1-0", "0-1", "1/2-1/2", "*"].contains(&symbol.as_str()) {
+                    if game.variations.is_empty() {
+                        let finished = std::mem::replace(&mut game, GameReader::new());
+                        games.push(finished.finish().map_err(|e| game_error(&games, e))?);
+                    }
+                    continue;
+                }
+
+                // Move numbers such as `12.` or `12...` may be written against the move
+                let digits = symbol.chars().take_while(|c| c.is_ascii_digit()).count();
+                let text = match symbol[digits..].trim_start_matches('.') {
+                    rest if rest.len() < symbol.len() - digits => rest,
+                    _ => &symbol,
+                };
+                if !text.is_empty() {
+                    game.add_move(text).map_err(|e| game_error(&games, e))?;
+                }
+            }
+        }
+    }
+
+    // The result is missing from the last game
+    if !game.is_empty() {
+        games.push(game.finish().map_err(|e| game_error(&games, e))?);
+    }
+    if games.is_empty() {
+        return Err(String::from("No games found"));
+    }
+    Ok(games)
+}
+
+/// Reads a tag pair after its `[`, up to and including the `]`, e.g. `White "Morphy, Paul"]`.
+///
+/// The value is read as a quoted string before looking for the `]`, so it may contain one, e.g.
+/// an IPv6 address such as `[::1]:8080`.
+fn parse_tag(chars: &mut Peekable<Chars>) -> Result<(String, String), String> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"\"]".contains(*c)) {
+        name.push(c);
+    }
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if name.is_empty() || chars.next() != Some('"') {
+        return Err(format!("Invalid tag: [{}", name));
+    }
+
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => value.extend(chars.next()),
+            Some(c) => value.push(c),
+            None => return Err(format!("Unterminated tag value: [{} \"{}", name, value)),
+        }
+    }
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    match chars.next() {
+        Some(']') => Ok((name, value)),
+        _ => Err(format!("Invalid tag value: [{} \"{}\"", name, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_rules::PieceKind;
+    use crate::game::{Opponent, PlayedMove};
+    use crate::game_tree::MoveNode;
+
+    /// Returns the moves given in SAN as played from the start position.
+    fn line(sans: &[&str]) -> Vec<PlayedMove> {
+        let mut position = Position::initial();
+        sans.iter()
+            .map(|san| {
+                let chess_move = position.parse_move(san).unwrap();
+                position.make_move(chess_move);
+                PlayedMove { chess_move, san: san.to_string(), elapsed: Duration::ZERO }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn written_games_read_back_with_their_moves_and_clocks() {
+        // The black pawn keeps the game going after the knight promotion
+        let start = Position::from_fen("4k3/P7/8/8/8/8/7p/4K3 w - - 0 1").unwrap();
+        let mut game = Game::from_position(Opponent::Local, start.clone());
+        game.metadata.white = String::from("Paul \"The Pride\" Morphy");
+        for uci in ["a7a8n", "e8e7", "a8c7", "e7d6", "c7b5", "d6c5"] {
+            assert!(game.play(ChessMove::from_uci(uci).unwrap()), "{} wasn't played", uci);
+        }
+        for (ply, played) in game.history.iter_mut().enumerate() {
+            played.elapsed = Duration::from_secs(ply as u64 * 61);
+        }
+
+        let games = parse(&write(&game)).unwrap();
+        assert_eq!(games.len(), 1);
+        let read = &games[0];
+        assert_eq!(read.start, start);
+        assert_eq!(read.tag("White"), Some("Paul \"The Pride\" Morphy"));
+        assert_eq!(read.tag("Result"), Some("*"));
+
+        let written: Vec<(ChessMove, &str)> = game.history.iter().map(|played| (played.chess_move, played.san.as_str())).collect();
+        let read_back: Vec<(ChessMove, &str)> = read.moves.iter().map(|pgn_move| (pgn_move.chess_move, pgn_move.san.as_str())).collect();
+        assert_eq!(read_back, written);
+        assert_eq!(read.moves[0].san, "a8=N");
+        assert_eq!(read.moves[0].chess_move.promotion, Some(PieceKind::Knight));
+
+        let clocks: Vec<Option<&str>> = read.moves.iter().map(|pgn_move| pgn_move.comment.as_deref()).collect();
+        assert_eq!(
+            clocks,
+            [
+                Some("[%emt 0:00:00]"),
+                Some("[%emt 0:01:01]"),
+                Some("[%emt 0:02:02]"),
+                Some("[%emt 0:03:03]"),
+                Some("[%emt 0:04:04]"),
+                Some("[%emt 0:05:05]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tag_values_may_contain_brackets_and_escapes() {
+        let games = parse("[Site \"[::1]:8080\"]\n[White \"\\\"Paul\\\" Morphy\"]\n\n1. e4 *").unwrap();
+        assert_eq!(games[0].tag("Site"), Some("[::1]:8080"));
+        assert_eq!(games[0].tag("White"), Some("\"Paul\" Morphy"));
+        assert_eq!(games[0].moves.len(), 1);
+    }
+
+    #[test]
+    fn variations_are_read_into_the_tree() {
+        let games = parse("1. e4 e5 (1... c5 2. Nf3 (2. c3) d6) 2. Nf3 {main} *").unwrap();
+        let game = &games[0];
+        let main: Vec<&str> = game.moves.iter().map(|pgn_move| pgn_move.san.as_str()).collect();
+        assert_eq!(main, ["e4", "e5", "Nf3"]);
+        assert_eq!(game.moves[2].comment.as_deref(), Some("main"));
+
+        let sans = |nodes: Vec<&MoveNode>| nodes.iter().map(|node| node.san.clone()).collect::<Vec<_>>();
+        assert_eq!(sans(game.tree.alternatives(&line(&["e4", "c5"]))), ["e5", "c5"]);
+        assert_eq!(sans(game.tree.continuation(&line(&["e4", "c5"]))), ["Nf3", "d6"]);
+        assert_eq!(sans(game.tree.alternatives(&line(&["e4", "c5", "c3"]))), ["Nf3", "c3"]);
+    }
+
+    #[test]
+    fn variations_before_the_first_move_are_errors() {
+        assert!(parse("(1. d4) 1. e4 *").is_err());
+    }
+
+    #[test]
+    fn unterminated_tags_are_errors() {
+        assert!(parse("[Site \"[::1]:8080\n1. e4 *").is_err());
+        assert!(parse("[Site \"somewhere\" 1. e4 *").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Replaying games read from PGN, with a chooser for files with several games.
+
+use piston::GenericEvent;
+use piston::input::{Button, Key, MouseButton};
+
+use crate::chess_controller::ChessController;
+use crate::chess_graphics::ChessGraphicsSettings;
+use crate::chess_rules::ChessMove;
+use crate::game::Opponent;
+use crate::pgn::PgnGame;
+
+/// Handles events for choosing a game from a PGN file with several games.
+pub struct GameChooser {
+    /// Games in the order they are in the file.
+    pub games: Vec<PgnGame>,
+    /// Index of the selected game.
+    pub selected: usize,
+    /// Stores the mouse coords.
+    mouse_coords: [f64; 2],
+}
+
+impl GameChooser {
+    /// Creates a game chooser with `selected` selected.
+    pub fn new(games: Vec<PgnGame>, selected: usize) -> GameChooser {
+        GameChooser {
+            games,
+            selected,
+            mouse_coords: [0.0; 2],
+        }
+    }
+
+    /// Returns the index of the first game shown, so the selected game stays in view.
+    pub fn first_shown(&self, settings: &ChessGraphicsSettings) -> usize {
+        let rows = settings.chooser_rows();
+        (self.selected + 1).saturating_sub(rows).min(self.games.len().saturating_sub(rows))
+    }
+
+    /// Handles events, returns the index of the game to replay once the player has picked one.
+    ///
+    /// Up and down or a click select a game, and Enter or clicking the selected game again opens
+    /// it.
+    pub fn event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) -> Option<usize> {
+        if let Some(pos) = e.mouse_cursor_args() {
+            self.mouse_coords = pos;
+        }
+
+        let last = self.games.len() - 1;
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Up)) => self.selected = self.selected.saturating_sub(1),
+            Some(Button::Keyboard(Key::Down)) => self.selected = (self.selected + 1).min(last),
+            Some(Button::Keyboard(Key::PageUp)) => {
+                self.selected = self.selected.saturating_sub(settings.chooser_rows());
+            }
+            Some(Button::Keyboard(Key::PageDown)) => {
+                self.selected = (self.selected + settings.chooser_rows()).min(last);
+            }
+            Some(Button::Keyboard(Key::Return)) => return Some(self.selected),
+            Some(Button::Mouse(MouseButton::Left)) => {
+                let first = self.first_shown(settings);
+                let shown = self.games.len() - first;
+                let rows = (0..shown.min(settings.chooser_rows())).map(|row| settings.chooser_row_rect(row));
+                if let Some(row) = settings.rect_at(self.mouse_coords, rows) {
+                    if first + row == self.selected {
+                        return Some(self.selected);
+                    }
+                    self.selected = first + row;
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+/// Handles events for stepping through a game read from PGN and its variations.
+pub struct ReplayViewer {
+    /// Every game of the file, to go back to the chooser.
+    pub games: Vec<PgnGame>,
+    /// Index of the game being replayed.
+    pub index: usize,
+    /// Moves of the line being viewed played on the board.
+    pub ply: usize,
+    /// Shows the board, the move list and analysis like during a game.
+    pub controller: ChessController,
+}
+
+impl ReplayViewer {
+    /// Creates a viewer showing the start of a game.
+    pub fn new(games: Vec<PgnGame>, index: usize) -> ReplayViewer {
+        let controller = Self::controller_at(&games[index], &[]);
+        let mut viewer = ReplayViewer {
+            games,
+            index,
+            ply: 0,
+            controller,
+        };
+        viewer.describe_ply();
+        viewer
+    }
+
+    /// Returns the game being replayed.
+    pub fn game(&self) -> &PgnGame {
+        &self.games[self.index]
+    }
+
+    /// Returns whether there is a chooser to go back to.
+    pub fn has_chooser(&self) -> bool {
+        self.games.len() > 1
+    }
+
+    /// Handles events, returns `true` when the player wants to go back to the chooser.
+    ///
+    /// Left and right step through the moves, Home and End jump to the start and the end of the
+    /// line, up and down switch the last move between its variations, and F4 turns analysis on
+    /// and off. Escape goes back to the chooser.
+    pub fn event<E: GenericEvent>(&mut self, e: &E) -> bool {
+        self.controller.game.update();
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Escape)) => return self.has_chooser(),
+            Some(Button::Keyboard(Key::Left)) => self.show_ply(self.ply.saturating_sub(1)),
+            Some(Button::Keyboard(Key::Right)) => self.show_ply(self.ply + 1),
+            Some(Button::Keyboard(Key::Home)) => self.show_ply(0),
+            Some(Button::Keyboard(Key::End)) => self.show_ply(self.line().len()),
+            Some(Button::Keyboard(Key::Up)) => self.switch_variation(false),
+            Some(Button::Keyboard(Key::Down)) => self.switch_variation(true),
+            Some(Button::Keyboard(Key::F4)) => self.controller.game.toggle_analysis(),
+            _ => {}
+        }
+        false
+    }
+
+    /// Returns the moves of the line being viewed, the moves played followed by the moves the
+    /// line goes on with.
+    fn line(&self) -> Vec<ChessMove> {
+        let history = &self.controller.game.history;
+        let mut line: Vec<ChessMove> = history.iter().map(|played| played.chess_move).collect();
+        line.extend(self.controller.tree.continuation(history).iter().map(|node| node.chess_move));
+        line
+    }
+
+    /// Shows the position after `ply` moves of the line being viewed.
+    fn show_ply(&mut self, ply: usize) {
+        let line = self.line();
+        let ply = ply.min(line.len());
+        if ply == self.ply {
+            return;
+        }
+
+        if ply == self.ply + 1 {
+            // Stepping forward plays the move, so it is announced like during a game
+            self.controller.game.apply_move(line[self.ply]);
+        } else {
+            let mut controller = Self::controller_at(self.game(), &line[..ply]);
+            controller.game.analysis = self.controller.game.analysis.take();
+            controller.game.analyse();
+            self.controller = controller;
+        }
+        self.ply = ply;
+        self.describe_ply();
+    }
+
+    /// Replaces the last move with the next or the previous variation played instead of it.
+    fn switch_variation(&mut self, next: bool) {
+        let history = &self.controller.game.history;
+        let alternatives: Vec<ChessMove> = self.controller.tree
+            .alternatives(history)
+            .iter()
+            .map(|node| node.chess_move)
+            .collect();
+        let last = match history.last() {
+            Some(played) if alternatives.len() > 1 => played.chess_move,
+            _ => return,
+        };
+
+        let index = alternatives.iter().position(|chess_move| *chess_move == last).unwrap_or(0);
+        let index = if next {
+            (index + 1) % alternatives.len()
+        } else {
+            (index + alternatives.len() - 1) % alternatives.len()
+        };
+        self.controller.game.take_back_to(self.ply - 1);
+        self.controller.game.apply_move(alternatives[index]);
+        self.describe_ply();
+    }
+
+    /// Returns a controller showing `game` after the moves of `line`, with its players from the
+    /// tags and its variations.
+    fn controller_at(game: &PgnGame, line: &[ChessMove]) -> ChessController {
+        let mut controller = ChessController::from_position(Opponent::Local, game.start.clone());
+        controller.tree = game.tree.clone();
+        let metadata = &mut controller.game.metadata;
+        for (name, value) in &game.tags {
+            let field = match name.as_str() {
+                "Event" => &mut metadata.event,
+                "Site" => &mut metadata.site,
+                "Date" => &mut metadata.date,
+                "White" => &mut metadata.white,
+                "Black" => &mut metadata.black,
+                "TimeControl" => &mut metadata.time_control,
+                _ => continue,
+            };
+            *field = value.clone();
+        }
+
+        // Jumping around would announce every move on the way
+        let announcer = controller.game.announcer.take();
+        for chess_move in line {
+            controller.game.apply_move(*chess_move);
+        }
+        controller.game.announcer = announcer;
+        controller
+    }
+
+    /// Shows the players, the move number, the variation and the comment of the last move in the
+    /// status bar.
+    fn describe_ply(&mut self) {
+        let game = &self.games[self.index];
+        let history = &self.controller.game.history;
+        let metadata = &self.controller.game.metadata;
+        let mut text = format!(
+            "{} - {}  |  Half move {} of {}",
+            metadata.white,
+            metadata.black,
+            self.ply,
+            self.ply + self.controller.tree.continuation(history).len()
+        );
+
+        let alternatives = self.controller.tree.alternatives(history);
+        match history.last() {
+            Some(last) if alternatives.len() > 1 => {
+                let index = alternatives.iter().position(|node| node.chess_move == last.chess_move).unwrap_or(0);
+                text.push_str(&format!("  |  Variation {} of {}", index + 1, alternatives.len()));
+            }
+            _ => {}
+        }
+
+        // Only moves of the main line have comments
+        let on_main_line = history
+            .iter()
+            .zip(&game.moves)
+            .all(|(played, pgn_move)| played.chess_move == pgn_move.chess_move);
+        let last = self.ply.checked_sub(1).and_then(|last| game.moves.get(last));
+        if let Some(comment) = last.and_then(|pgn_move| pgn_move.comment.as_ref()).filter(|_| on_main_line) {
+            text.push_str(&format!("  |  {}", comment));
+        }
+        self.controller.game.notice = Some(text);
+    }
+}
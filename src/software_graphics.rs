@@ -0,0 +1,329 @@
+//! Graphics backend that rasterizes into an image in memory, so the board can be drawn without a
+//! GPU, e.g. for screenshots on machines without OpenGL.
+//!
+//! Only what `ChessGraphics` needs is supported: alpha blending, scissor rectangles and textures
+//! sampled without filtering. Stencil operations are ignored.
+
+use graphics::draw_state::DrawState;
+use graphics::types::Color;
+use graphics::{Context, Graphics, ImageSize};
+use image::{Rgba, RgbaImage};
+use texture::{CreateTexture, Format, TextureOp, TextureSettings, UpdateTexture};
+
+/// A texture kept in memory as RGBA pixels.
+pub struct SoftwareTexture {
+    image: RgbaImage,
+}
+
+impl SoftwareTexture {
+    /// Returns the texel at `uv`, where both coordinates go from 0 to 1 across the texture.
+    fn sample(&self, uv: [f32; 2]) -> Color {
+        let (width, height) = self.image.dimensions();
+        let x = ((uv[0] * width as f32) as u32).min(width.saturating_sub(1));
+        let y = ((uv[1] * height as f32) as u32).min(height.saturating_sub(1));
+        let Rgba(texel) = *self.image.get_pixel(x, y);
+        texel.map(|channel| channel as f32 / 255.0)
+    }
+}
+
+impl ImageSize for SoftwareTexture {
+    fn get_size(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+}
+
+impl TextureOp<()> for SoftwareTexture {
+    type Error = String;
+}
+
+impl CreateTexture<()> for SoftwareTexture {
+    fn create<S: Into<[u32; 2]>>(
+        _factory: &mut (),
+        _format: Format,
+        memory: &[u8],
+        size: S,
+        _settings: &TextureSettings,
+    ) -> Result<Self, Self::Error> {
+        let [width, height] = size.into();
+        RgbaImage::from_raw(width, height, memory.to_vec())
+            .map(|image| SoftwareTexture { image })
+            .ok_or_else(|| format!("Expected {} bytes of RGBA pixels, got {}", width * height * 4, memory.len()))
+    }
+}
+
+impl UpdateTexture<()> for SoftwareTexture {
+    fn update<O: Into<[u32; 2]>, S: Into<[u32; 2]>>(
+        &mut self,
+        _factory: &mut (),
+        _format: Format,
+        memory: &[u8],
+        offset: O,
+        size: S,
+    ) -> Result<(), Self::Error> {
+        let [offset_x, offset_y] = offset.into();
+        let [width, height] = size.into();
+        let update = RgbaImage::from_raw(width, height, memory.to_vec())
+            .ok_or_else(|| format!("Expected {} bytes of RGBA pixels, got {}", width * height * 4, memory.len()))?;
+
+        for (x, y, pixel) in update.enumerate_pixels() {
+            if let Some(target) = self.image.get_pixel_mut_checked(offset_x + x, offset_y + y) {
+                *target = *pixel;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws into an RGBA image with the same calls as `GlGraphics`.
+pub struct SoftwareGraphics {
+    image: RgbaImage,
+}
+
+impl SoftwareGraphics {
+    /// Creates a transparent image of `width` by `height` pixels to draw into.
+    pub fn new(width: u32, height: u32) -> SoftwareGraphics {
+        SoftwareGraphics {
+            image: RgbaImage::new(width, height),
+        }
+    }
+
+    /// Calls `f` with a context that maps window coordinates to pixels of the image, like
+    /// `GlGraphics::draw` does for the viewport.
+    pub fn draw<F: FnOnce(Context, &mut SoftwareGraphics)>(&mut self, f: F) {
+        let (width, height) = self.image.dimensions();
+        f(Context::new_abs(width as f64, height as f64), self);
+    }
+
+    /// Returns what has been drawn.
+    pub fn into_image(self) -> RgbaImage {
+        self.image
+    }
+
+    /// Fills every triangle in `vertices`, three at a time, with the color `shade` returns for the
+    /// triangle's first vertex index and the barycentric weights of each pixel center.
+    fn fill_triangles<S: FnMut(usize, [f32; 3]) -> Color>(
+        &mut self,
+        draw_state: &DrawState,
+        vertices: &[[f32; 2]],
+        mut shade: S,
+    ) {
+        let (width, height) = self.image.dimensions();
+        // Pixels outside the scissor rectangle are never touched
+        let [clip_x, clip_y, clip_width, clip_height] = draw_state.scissor.unwrap_or([0, 0, width, height]);
+        let clip = [
+            clip_x.min(width),
+            clip_y.min(height),
+            clip_x.saturating_add(clip_width).min(width),
+            clip_y.saturating_add(clip_height).min(height),
+        ];
+
+        // Vertices are in normalized device coordinates, with y pointing up
+        let to_pixels = |[x, y]: [f32; 2]| [(x + 1.0) * 0.5 * width as f32, (1.0 - y) * 0.5 * height as f32];
+
+        for (triangle, corners) in vertices.chunks_exact(3).enumerate() {
+            let first = triangle * 3;
+            let mut points = [to_pixels(corners[0]), to_pixels(corners[1]), to_pixels(corners[2])];
+            let mut order = [0, 1, 2];
+            let area = edge(points[0], points[1], points[2]);
+            if area == 0.0 {
+                continue;
+            }
+            // Wind every triangle the same way, so inside means positive edge values
+            if area < 0.0 {
+                points.swap(1, 2);
+                order.swap(1, 2);
+            }
+            let area = area.abs();
+
+            let min_x = points.iter().map(|p| p[0]).fold(f32::INFINITY, f32::min).floor().max(clip[0] as f32) as u32;
+            let min_y = points.iter().map(|p| p[1]).fold(f32::INFINITY, f32::min).floor().max(clip[1] as f32) as u32;
+            let max_x = points.iter().map(|p| p[0]).fold(f32::NEG_INFINITY, f32::max).ceil().min(clip[2] as f32) as u32;
+            let max_y = points.iter().map(|p| p[1]).fold(f32::NEG_INFINITY, f32::max).ceil().min(clip[3] as f32) as u32;
+
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    let center = [x as f32 + 0.5, y as f32 + 0.5];
+                    let weights = [
+                        edge(points[1], points[2], center),
+                        edge(points[2], points[0], center),
+                        edge(points[0], points[1], center),
+                    ];
+                    let edges = [(points[1], points[2]), (points[2], points[0]), (points[0], points[1])];
+                    let inside = weights
+                        .iter()
+                        .zip(edges)
+                        .all(|(weight, (from, to))| *weight > 0.0 || (*weight == 0.0 && owns_edge(from, to)));
+                    if !inside {
+                        continue;
+                    }
+
+                    let mut barycentric = [0.0; 3];
+                    for (corner, weight) in order.iter().zip(weights) {
+                        barycentric[*corner] = weight / area;
+                    }
+                    let color = shade(first, barycentric);
+                    blend(self.image.get_pixel_mut(x, y), color);
+                }
+            }
+        }
+    }
+}
+
+/// Returns twice the signed area of the triangle `a`, `b`, `p`.
+fn edge(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// Decides which of two triangles sharing an edge draws the pixels exactly on it.
+///
+/// Neighbouring triangles go along a shared edge in opposite directions, so exactly one of them
+/// owns it and translucent shapes made of several triangles aren't blended twice along seams.
+fn owns_edge(from: [f32; 2], to: [f32; 2]) -> bool {
+    let [dx, dy] = [to[0] - from[0], to[1] - from[1]];
+    dy > 0.0 || (dy == 0.0 && dx < 0.0)
+}
+
+/// Draws `color` over `pixel` using its alpha.
+fn blend(pixel: &mut Rgba<u8>, color: Color) {
+    let alpha = color[3].clamp(0.0, 1.0);
+    let Rgba([red, green, blue, pixel_alpha]) = *pixel;
+    let mix = |source: f32, target: u8| {
+        let target = target as f32 / 255.0;
+        ((source.clamp(0.0, 1.0) * alpha + target * (1.0 - alpha)) * 255.0).round() as u8
+    };
+    let pixel_alpha = pixel_alpha as f32 / 255.0;
+    *pixel = Rgba([
+        mix(color[0], red),
+        mix(color[1], green),
+        mix(color[2], blue),
+        ((alpha + pixel_alpha * (1.0 - alpha)) * 255.0).round() as u8,
+    ]);
+}
+
+/// Multiplies two colors channel by channel.
+fn tint(a: Color, b: Color) -> Color {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2], a[3] * b[3]]
+}
+
+/// Interpolates per-vertex values with barycentric weights.
+fn interpolate<const N: usize>(values: &[[f32; N]], first: usize, barycentric: [f32; 3]) -> [f32; N] {
+    let mut result = [0.0; N];
+    for (corner, weight) in barycentric.iter().enumerate() {
+        for (channel, value) in result.iter_mut().zip(values[first + corner]) {
+            *channel += value * weight;
+        }
+    }
+    result
+}
+
+impl Graphics for SoftwareGraphics {
+    type Texture = SoftwareTexture;
+
+    fn clear_color(&mut self, color: Color) {
+        let pixel = Rgba(color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8));
+        for target in self.image.pixels_mut() {
+            *target = pixel;
+        }
+    }
+
+    fn clear_stencil(&mut self, _value: u8) {}
+
+    fn tri_list<F>(&mut self, draw_state: &DrawState, color: &[f32; 4], mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]])),
+    {
+        f(&mut |vertices| self.fill_triangles(draw_state, vertices, |_, _| *color));
+    }
+
+    fn tri_list_c<F>(&mut self, draw_state: &DrawState, mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]])),
+    {
+        f(&mut |vertices, colors| {
+            self.fill_triangles(draw_state, vertices, |first, barycentric| {
+                interpolate(colors, first, barycentric)
+            })
+        });
+    }
+
+    fn tri_list_uv<F>(&mut self, draw_state: &DrawState, color: &[f32; 4], texture: &SoftwareTexture, mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])),
+    {
+        f(&mut |vertices, uvs| {
+            self.fill_triangles(draw_state, vertices, |first, barycentric| {
+                tint(*color, texture.sample(interpolate(uvs, first, barycentric)))
+            })
+        });
+    }
+
+    fn tri_list_uv_c<F>(&mut self, draw_state: &DrawState, texture: &SoftwareTexture, mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])),
+    {
+        f(&mut |vertices, uvs, colors| {
+            self.fill_triangles(draw_state, vertices, |first, barycentric| {
+                let color = interpolate(colors, first, barycentric);
+                tint(color, texture.sample(interpolate(uvs, first, barycentric)))
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphics::clear;
+
+    use super::*;
+    use crate::chess_controller::ChessController;
+    use crate::chess_graphics::{ChessGraphics, ChessGraphicsSettings};
+    use crate::game::Opponent;
+
+    const BACKGROUND: Color = [0.4, 0.4, 0.4, 1.0];
+
+    /// Draws the start position the way screenshots are taken.
+    fn start_position() -> (ChessGraphicsSettings, RgbaImage) {
+        let chess_view: ChessGraphics<SoftwareTexture> = ChessGraphics::new(ChessGraphicsSettings::new()).unwrap();
+        let controller = ChessController::new(Opponent::Local);
+        let [width, height] = chess_view.settings.window_size();
+        let mut g = SoftwareGraphics::new(width.ceil() as u32, height.ceil() as u32);
+        g.draw(|c, g| {
+            clear(BACKGROUND, g);
+            chess_view.draw(&controller, &c, g);
+        });
+        (chess_view.settings, g.into_image())
+    }
+
+    fn pixel(image: &RgbaImage, [x, y]: [f64; 2]) -> Rgba<u8> {
+        *image.get_pixel(x as u32, y as u32)
+    }
+
+    fn rgba(color: Color) -> Rgba<u8> {
+        Rgba(color.map(|channel| (channel * 255.0).round() as u8))
+    }
+
+    #[test]
+    fn empty_squares_have_their_colors() {
+        let (settings, image) = start_position();
+        // e4 is light and d4 dark
+        assert_eq!(pixel(&image, settings.square_center(28)), rgba(settings.white_color));
+        assert_eq!(pixel(&image, settings.square_center(27)), rgba(settings.black_color));
+        // The corners of a square are never covered by a piece either
+        let [x, y, side, _] = settings.square_rect(35);
+        assert_eq!(pixel(&image, [x + 1.0, y + side - 1.0]), rgba(settings.white_color));
+    }
+
+    #[test]
+    fn pieces_cover_their_squares() {
+        let (settings, image) = start_position();
+        // The kings on e1 and e8, on a dark and a light square
+        assert_ne!(pixel(&image, settings.square_center(4)), rgba(settings.black_color));
+        assert_ne!(pixel(&image, settings.square_center(60)), rgba(settings.white_color));
+    }
+
+    #[test]
+    fn background_is_left_around_the_board() {
+        let (settings, image) = start_position();
+        assert_eq!(pixel(&image, [settings.offset[0] / 2.0, settings.offset[1] / 2.0]), rgba(BACKGROUND));
+    }
+}
@@ -0,0 +1,251 @@
+//! Start menu and waiting screen shown before a game.
+
+use std::path::PathBuf;
+
+use piston::GenericEvent;
+use piston::input::{Button, Key, MouseButton};
+
+use crate::chess_graphics::ChessGraphicsSettings;
+use crate::chess_rules::Position;
+use crate::networking::{Networking, PendingConnection};
+use crate::pgn;
+
+/// A game picked in the start menu.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameMode {
+    /// Host a network game on an address such as `0.0.0.0:8080`.
+    Host { address: String },
+    /// Join a network game hosted on an address such as `192.168.0.2:8080`.
+    Join { address: String },
+    /// Play both sides on this board.
+    Local,
+    /// Play against a UCI engine started with `command`.
+    Engine { command: String },
+    /// Set up a position in the board editor, to host a game on `host_address` or play the engine
+    /// started with `engine_command` from it.
+    Editor { host_address: String, engine_command: String },
+    /// Step through the games of a PGN file.
+    Replay { path: PathBuf },
+}
+
+/// An entry in the start menu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuItem {
+    Host,
+    Join,
+    Local,
+    Engine,
+    Editor,
+    LoadPgn,
+}
+
+impl MenuItem {
+    /// Returns the text shown for the entry.
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuItem::Host => "Host a game",
+            MenuItem::Join => "Join a game",
+            MenuItem::Local => "Local game",
+            MenuItem::Engine => "Play the engine",
+            MenuItem::Editor => "Set up a position",
+            MenuItem::LoadPgn => "Replay a PGN file",
+        }
+    }
+
+    /// Returns the name of the entry's text field, if it has one.
+    pub fn field_name(self) -> Option<&'static str> {
+        match self {
+            MenuItem::Host => Some("Port"),
+            MenuItem::Join => Some("Address"),
+            MenuItem::Local | MenuItem::Editor => None,
+            MenuItem::Engine => Some("Command"),
+            MenuItem::LoadPgn => Some("File"),
+        }
+    }
+}
+
+/// Handles events for the start menu.
+pub struct StartMenu {
+    /// Entries in the order they are shown.
+    pub items: Vec<MenuItem>,
+    /// Index of the selected entry.
+    pub selected: usize,
+    /// Port to host on.
+    pub host_port: String,
+    /// Address of the host to join.
+    pub join_address: String,
+    /// Command that starts the engine.
+    pub engine_command: String,
+    /// PGN file to replay.
+    pub pgn_path: String,
+    /// Shown below the entries, e.g. why the last game couldn't be started.
+    pub message: Option<String>,
+    /// Stores the mouse coords.
+    mouse_coords: [f64; 2],
+}
+
+impl StartMenu {
+    /// Creates a new start menu.
+    pub fn new() -> StartMenu {
+        StartMenu {
+            items: vec![
+                MenuItem::Host,
+                MenuItem::Join,
+                MenuItem::Local,
+                MenuItem::Engine,
+                MenuItem::Editor,
+                MenuItem::LoadPgn,
+            ],
+            selected: 0,
+            host_port: String::from("8080"),
+            join_address: String::from("127.0.0.1:8080"),
+            engine_command: String::from("stockfish"),
+            // Saved games are in the games directory
+            pgn_path: pgn::games_dir().join("").display().to_string(),
+            message: None,
+            mouse_coords: [0.0; 2],
+        }
+    }
+
+    /// Returns the text of an entry's field, if it has one.
+    pub fn field(&self, item: MenuItem) -> Option<&String> {
+        match item {
+            MenuItem::Host => Some(&self.host_port),
+            MenuItem::Join => Some(&self.join_address),
+            MenuItem::Local | MenuItem::Editor => None,
+            MenuItem::Engine => Some(&self.engine_command),
+            MenuItem::LoadPgn => Some(&self.pgn_path),
+        }
+    }
+
+    fn field_mut(&mut self, item: MenuItem) -> Option<&mut String> {
+        match item {
+            MenuItem::Host => Some(&mut self.host_port),
+            MenuItem::Join => Some(&mut self.join_address),
+            MenuItem::Local | MenuItem::Editor => None,
+            MenuItem::Engine => Some(&mut self.engine_command),
+            MenuItem::LoadPgn => Some(&mut self.pgn_path),
+        }
+    }
+
+    /// Handles events, returns the game to start once the player has picked one.
+    ///
+    /// Up and down or a click select an entry, typing edits its field, and Enter or clicking the
+    /// selected entry again starts the game.
+    pub fn event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) -> Option<GameMode> {
+        let item = self.items[self.selected];
+
+        if let Some(pos) = e.mouse_cursor_args() {
+            self.mouse_coords = pos;
+        }
+
+        if let Some(text) = e.text_args() {
+            if let Some(field) = self.field_mut(item) {
+                field.extend(text.chars().filter(|c| !c.is_control()));
+            }
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Up)) => {
+                self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+            }
+            Some(Button::Keyboard(Key::Down)) | Some(Button::Keyboard(Key::Tab)) => {
+                self.selected = (self.selected + 1) % self.items.len();
+            }
+            Some(Button::Keyboard(Key::Backspace)) => {
+                if let Some(field) = self.field_mut(item) {
+                    field.pop();
+                }
+            }
+            Some(Button::Keyboard(Key::Return)) => return self.game_mode(item),
+            Some(Button::Mouse(MouseButton::Left)) => {
+                if let Some(index) = settings.menu_item_at(self.mouse_coords, self.items.len()) {
+                    if index == self.selected {
+                        return self.game_mode(item);
+                    }
+                    self.selected = index;
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Returns the game for an entry, or sets a message if its field isn't filled in.
+    fn game_mode(&mut self, item: MenuItem) -> Option<GameMode> {
+        let field = self.field(item).map(|field| field.trim().to_string());
+        if field.as_deref() == Some("") {
+            self.message = Some(format!("{} can't be empty", item.field_name().unwrap()));
+            return None;
+        }
+
+        let mode = match item {
+            MenuItem::Host => GameMode::Host { address: format!("0.0.0.0:{}", field.unwrap()) },
+            MenuItem::Join => GameMode::Join { address: field.unwrap() },
+            MenuItem::Local => GameMode::Local,
+            MenuItem::Engine => GameMode::Engine { command: field.unwrap() },
+            // The editor can start the other games, with what is filled in for them
+            MenuItem::Editor => GameMode::Editor {
+                host_address: format!("0.0.0.0:{}", self.host_port.trim()),
+                engine_command: self.engine_command.trim().to_string(),
+            },
+            MenuItem::LoadPgn => GameMode::Replay { path: PathBuf::from(field.unwrap()) },
+        };
+        self.message = None;
+        Some(mode)
+    }
+}
+
+/// What happened while waiting for a network game.
+pub enum WaitingOutcome {
+    Connected(Networking),
+    Failed(String),
+    Cancelled,
+}
+
+/// Handles events while waiting for a network game to connect.
+pub struct WaitingScreen {
+    /// The connection being set up.
+    pub pending: PendingConnection,
+    /// What is being waited for, e.g. the address listened on.
+    pub description: String,
+    /// Position the game starts from when hosting, a joined game starts from the host's.
+    pub start: Position,
+    /// Stores the mouse coords.
+    mouse_coords: [f64; 2],
+}
+
+impl WaitingScreen {
+    /// Creates a new waiting screen.
+    pub fn new(pending: PendingConnection, description: String, start: Position) -> WaitingScreen {
+        WaitingScreen {
+            pending,
+            description,
+            start,
+            mouse_coords: [0.0; 2],
+        }
+    }
+
+    /// Handles events, returns the outcome once connected, failed or cancelled.
+    ///
+    /// Escape or clicking the cancel button stops waiting.
+    pub fn event<E: GenericEvent>(&mut self, settings: &ChessGraphicsSettings, e: &E) -> Option<WaitingOutcome> {
+        if let Some(pos) = e.mouse_cursor_args() {
+            self.mouse_coords = pos;
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Escape)) => return Some(WaitingOutcome::Cancelled),
+            Some(Button::Mouse(MouseButton::Left)) if settings.cancel_button_contains(self.mouse_coords) => {
+                return Some(WaitingOutcome::Cancelled);
+            }
+            _ => {}
+        }
+
+        match self.pending.poll()? {
+            Ok(networking) => Some(WaitingOutcome::Connected(networking)),
+            Err(e) => Some(WaitingOutcome::Failed(format!("Could not connect: {}", e))),
+        }
+    }
+}
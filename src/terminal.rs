@@ -0,0 +1,188 @@
+//! Terminal front-end, drawing the board with Unicode pieces and ANSI colors and playing typed
+//! moves, e.g. to play over SSH or on a machine without a display.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::chess_graphics::piece_glyph;
+use crate::chess_rules::{make_piece, piece_kind, piece_side, Side};
+use crate::game::{Game, Opponent};
+use crate::networking::{Networking, PendingConnection};
+use crate::start_menu::GameMode;
+use crate::uci_engine::UciEngine;
+
+/// How often the game is updated while waiting for input.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How many full moves are listed below the board.
+const SHOWN_FULL_MOVES: usize = 8;
+
+/// How many of the latest chat messages are listed below the moves.
+const SHOWN_CHAT_LINES: usize = 5;
+
+const LIGHT_SQUARE: &str = "\x1b[48;5;223m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const WHITE_PIECE: &str = "\x1b[97m";
+const BLACK_PIECE: &str = "\x1b[30m";
+const RESET: &str = "\x1b[0m";
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Starts a game in the terminal and plays it until the player quits.
+pub fn play(mode: GameMode) -> Result<(), String> {
+    let opponent = match mode {
+        GameMode::Host { address } => {
+            let pending = PendingConnection::host(&address)
+                .map_err(|e| format!("Could not host on {}: {}", address, e))?;
+            Opponent::Network(connect(pending, &format!("Waiting for an opponent to connect on {}", address))?)
+        }
+        GameMode::Join { address } => {
+            let pending = PendingConnection::join(&address);
+            Opponent::Network(connect(pending, &format!("Connecting to {}", address))?)
+        }
+        GameMode::Local => Opponent::Local,
+        GameMode::Engine { command } => Opponent::Engine(
+            UciEngine::start(&command).map_err(|e| format!("Could not start engine '{}': {}", command, e))?,
+        ),
+        GameMode::Editor { .. } => return Err(String::from("The board editor needs the window")),
+        GameMode::Replay { .. } => return Err(String::from("Replays need the window")),
+    };
+
+    run(Game::new(opponent)).map_err(|e| format!("Terminal error: {}", e))
+}
+
+/// Waits until a network game is connected.
+fn connect(mut pending: PendingConnection, description: &str) -> Result<Networking, String> {
+    println!("{}, press Ctrl+C to give up", description);
+    loop {
+        match pending.poll() {
+            Some(Ok(networking)) => return Ok(networking),
+            Some(Err(e)) => return Err(format!("Could not connect: {}", e)),
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Plays `game` with moves typed on stdin, redrawing the screen whenever it changes.
+///
+/// Lines are read on a background thread so the opponent's moves show up while the player is
+/// typing. Besides moves, `takeback` takes back the last move, `accept` and `decline` answer the
+/// opponent's takeback request, `analysis` turns engine analysis on and off, `say` followed by a
+/// message chats in network games, `pgn` saves the game and `quit` ends the game.
+pub fn run(mut game: Game) -> io::Result<()> {
+    let (sender, input) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = io::stdout();
+    let mut shown = String::new();
+    loop {
+        game.update();
+
+        match input.try_recv() {
+            Ok(line) => {
+                // Redraw after every line, so the typed text doesn't stay below the prompt
+                shown.clear();
+                match line.trim() {
+                    "" => {}
+                    "quit" | "exit" => return Ok(()),
+                    "analysis" => game.toggle_analysis(),
+                    "pgn" => game.save_pgn(),
+                    "takeback" => game.takeback(),
+                    "accept" => game.answer_takeback(true),
+                    "decline" => game.answer_takeback(false),
+                    text if text.starts_with("say ") => game.send_chat(&text[4..]),
+                    text => {
+                        game.play_typed(text);
+                    }
+                }
+            }
+            Err(TryRecvError::Empty) => {}
+            // Stdin was closed
+            Err(TryRecvError::Disconnected) => return Ok(()),
+        }
+
+        let screen = render(&game);
+        if screen != shown {
+            write!(stdout, "{}{}", CLEAR_SCREEN, screen)?;
+            stdout.flush()?;
+            shown = screen;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Draws the board from White's side, the latest moves, analysis and the status line.
+pub fn render(game: &Game) -> String {
+    let mut screen = String::new();
+
+    for rank in (0..8u8).rev() {
+        let _ = write!(screen, "{} ", rank + 1);
+        for file in 0..8u8 {
+            let square = file + rank * 8;
+            let background = if (file + rank) % 2 == 1 { LIGHT_SQUARE } else { DARK_SQUARE };
+            let piece = game.position.board[square as usize];
+            // The solid black glyphs are drawn in both colors, they read better than outlines
+            let glyph = piece_kind(piece).and_then(|kind| piece_glyph(make_piece(Side::Black, kind)));
+            let foreground = if piece_side(piece) == Some(Side::White) { WHITE_PIECE } else { BLACK_PIECE };
+            let _ = write!(screen, "{}{} {} ", background, foreground, glyph.unwrap_or(' '));
+        }
+        let _ = writeln!(screen, "{}", RESET);
+    }
+    let _ = writeln!(screen, "   a  b  c  d  e  f  g  h\n");
+
+    // Full moves start with White's move, which is missing from the first one if Black moved first
+    let offset = game.first_move_offset();
+    let first = ((game.history.len() + offset).saturating_sub(SHOWN_FULL_MOVES * 2) / 2 * 2).saturating_sub(offset);
+    let mut moves = String::new();
+    for (index, played_move) in game.history.iter().enumerate().skip(first) {
+        if (index + offset) % 2 == 0 {
+            let _ = write!(moves, "{}. ", game.move_number(index));
+        } else if index == first {
+            let _ = write!(moves, "{}... ", game.move_number(index));
+        }
+        let _ = write!(moves, "{} ", played_move.san);
+    }
+    if !moves.is_empty() {
+        let _ = writeln!(screen, "{}\n", moves.trim_end());
+    }
+
+    if let Some(analysis) = &game.analysis {
+        match analysis.depth {
+            0 => screen.push_str("Analysing...\n"),
+            depth => {
+                let _ = writeln!(screen, "Depth {}", depth);
+            }
+        }
+        for line in &analysis.lines {
+            let _ = writeln!(screen, "{}  {}", line.score, line.san.join(" "));
+        }
+        screen.push('\n');
+    }
+
+    if game.has_chat() && !game.chat.is_empty() {
+        for line in &game.chat[game.chat.len().saturating_sub(SHOWN_CHAT_LINES)..] {
+            let _ = writeln!(screen, "{}: {}", line.sender, line.text);
+        }
+        screen.push('\n');
+    }
+
+    let _ = writeln!(screen, "{}", game.status_text());
+    if let Some(prompt) = game.takeback_prompt() {
+        let _ = writeln!(screen, "{}, type 'accept' or 'decline'", prompt);
+    }
+    if game.has_chat() {
+        screen.push_str("Type a move like Nf3 or g1f3, 'say' and a message, 'takeback', 'analysis', 'pgn' or 'quit': ");
+    } else {
+        screen.push_str("Type a move like Nf3 or g1f3, 'takeback', 'analysis', 'pgn' or 'quit': ");
+    }
+    screen
+}
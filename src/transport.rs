@@ -0,0 +1,259 @@
+//! Ways of getting encoded messages to the other side of a network game.
+//!
+//! `Networking` only speaks the protocol, and is given a `Transport` when it is created: TCP for
+//! games between builds of this and other implementations, WebSocket for browser clients, and an
+//! in-process channel for tests and local play. Every transport is non-blocking once set up.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::time::Duration;
+
+use tungstenite::WebSocket;
+
+/// How long the WebSocket handshake may take before connecting fails.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest message accepted over TCP, so a broken length doesn't fill the memory.
+const MAX_MESSAGE_LENGTH: usize = 1 << 20;
+
+/// Most bytes a varint length takes.
+const MAX_VARINT_LENGTH: usize = 10;
+
+/// Carries whole encoded messages to and from the opponent.
+pub trait Transport: Send {
+    /// Sends one encoded message.
+    fn send(&mut self, message: &[u8]) -> io::Result<()>;
+
+    /// Returns the next message received, or `None` if none has arrived yet. Never blocks.
+    ///
+    /// Fails once the connection is closed.
+    fn poll(&mut self) -> io::Result<Option<Vec<u8>>>;
+
+    /// Describes the other side, e.g. its address.
+    fn peer(&self) -> Option<String>;
+
+    /// Closes the connection, which the other side notices when it polls next.
+    fn close(&mut self);
+}
+
+/// Messages over TCP, each preceded by its length as a varint, the way prost's
+/// `encode_length_delimited` writes them, so one `poll` returns exactly one message however the
+/// stream splits or joins them.
+///
+/// Builds before protocol version 2 sent messages with nothing around them, and can't play
+/// against this one over TCP.
+pub struct TcpTransport {
+    stream: TcpStream,
+    /// Bytes read that don't make up a whole message yet.
+    buffer: Vec<u8>,
+    /// Bytes sent that the socket hasn't taken yet, written on the next send or poll so frames
+    /// are never cut off.
+    outgoing: Vec<u8>,
+}
+
+impl TcpTransport {
+    /// Wraps a connected stream, making it non-blocking.
+    pub fn new(stream: TcpStream) -> io::Result<TcpTransport> {
+        stream.set_nonblocking(true)?;
+        Ok(TcpTransport { stream, buffer: Vec::new(), outgoing: Vec::new() })
+    }
+
+    /// Writes as much of the outgoing bytes as the socket takes without blocking.
+    fn flush_outgoing(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "The connection was closed")),
+                Ok(length) => {
+                    self.outgoing.drain(..length);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the first message off the read buffer, if all of it has arrived.
+    fn take_message(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = &self.buffer[..];
+        let length = match prost::encoding::decode_varint(&mut header) {
+            Ok(length) => length as usize,
+            // Not all of the length has arrived, a varint ends with a byte below 0x80
+            Err(_) if self.buffer.len() < MAX_VARINT_LENGTH && self.buffer.iter().all(|byte| *byte >= 0x80) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        if length > MAX_MESSAGE_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The message is {} bytes long, more than the {} allowed", length, MAX_MESSAGE_LENGTH),
+            ));
+        }
+
+        let start = self.buffer.len() - header.len();
+        if self.buffer.len() < start + length {
+            return Ok(None);
+        }
+        let message = self.buffer[start..start + length].to_vec();
+        self.buffer.drain(..start + length);
+        Ok(Some(message))
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.outgoing.reserve(message.len() + MAX_VARINT_LENGTH);
+        prost::encoding::encode_varint(message.len() as u64, &mut self.outgoing);
+        self.outgoing.extend_from_slice(message);
+        self.flush_outgoing()
+    }
+
+    fn poll(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.flush_outgoing()?;
+        loop {
+            if let Some(message) = self.take_message()? {
+                return Ok(Some(message));
+            }
+            let mut buf = [0u8; 4096];
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "The connection was closed")),
+                Ok(length) => self.buffer.extend_from_slice(&buf[..length]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn peer(&self) -> Option<String> {
+        self.stream.peer_addr().ok().map(|address| address.to_string())
+    }
+
+    fn close(&mut self) {
+        let _ = self.flush_outgoing();
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Messages between two ends in the same process, e.g. a host and a client in a test.
+pub struct ChannelTransport {
+    sender: Option<Sender<Vec<u8>>>,
+    receiver: Receiver<Vec<u8>>,
+    /// Name of the other end.
+    name: String,
+}
+
+impl ChannelTransport {
+    /// Returns two connected ends, the first for the host and the second for the client.
+    pub fn pair() -> (ChannelTransport, ChannelTransport) {
+        let (host_sender, client_receiver) = mpsc::channel();
+        let (client_sender, host_receiver) = mpsc::channel();
+        (
+            ChannelTransport { sender: Some(host_sender), receiver: host_receiver, name: String::from("client") },
+            ChannelTransport { sender: Some(client_sender), receiver: client_receiver, name: String::from("host") },
+        )
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "The other end was closed");
+        let sender = self.sender.as_ref().ok_or_else(closed)?;
+        sender.send(message.to_vec()).map_err(|_| closed())
+    }
+
+    fn poll(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.receiver.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                Err(io::Error::new(io::ErrorKind::ConnectionAborted, "The other end was closed"))
+            }
+        }
+    }
+
+    fn peer(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn close(&mut self) {
+        self.sender = None;
+    }
+}
+
+/// Messages as binary WebSocket frames, so browsers can connect.
+pub struct WebSocketTransport {
+    socket: WebSocket<TcpStream>,
+}
+
+impl WebSocketTransport {
+    /// Connects to the host at a `ws://` URL, blocking until the handshake is done.
+    pub fn connect(url: &str) -> io::Result<WebSocketTransport> {
+        let address = url.trim_start_matches("ws://").split('/').next().unwrap_or_default();
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let (socket, _) = tungstenite::client(url, stream).map_err(handshake_error)?;
+        WebSocketTransport::from_socket(socket)
+    }
+
+    /// Answers the handshake of a client that connected to the host, blocking until it is done.
+    pub fn accept(stream: TcpStream) -> io::Result<WebSocketTransport> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let socket = tungstenite::accept(stream).map_err(handshake_error)?;
+        WebSocketTransport::from_socket(socket)
+    }
+
+    /// Makes a WebSocket that has finished its handshake non-blocking.
+    fn from_socket(socket: WebSocket<TcpStream>) -> io::Result<WebSocketTransport> {
+        socket.get_ref().set_read_timeout(None)?;
+        socket.get_ref().set_nonblocking(true)?;
+        Ok(WebSocketTransport { socket })
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self.socket.send(tungstenite::Message::Binary(message.to_vec())) {
+            Ok(()) => Ok(()),
+            // The frame is queued and written on the next send or poll
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(websocket_error(e)),
+        }
+    }
+
+    fn poll(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.socket.read() {
+            Ok(tungstenite::Message::Binary(message)) => Ok(Some(message)),
+            Ok(tungstenite::Message::Text(text)) => Ok(Some(text.into_bytes())),
+            // Pings are answered by tungstenite
+            Ok(_) => Ok(None),
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(websocket_error(e)),
+        }
+    }
+
+    fn peer(&self) -> Option<String> {
+        self.socket.get_ref().peer_addr().ok().map(|address| address.to_string())
+    }
+
+    fn close(&mut self) {
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+    }
+}
+
+/// Turns a failed WebSocket handshake into an IO error.
+fn handshake_error<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::other(format!("WebSocket handshake failed: {}", e))
+}
+
+/// Turns a WebSocket error into an IO error.
+fn websocket_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
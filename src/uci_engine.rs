@@ -0,0 +1,288 @@
+//! UCI chess engine running as a child process.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::chess_rules::{ChessMove, Position};
+
+/// Evaluation reported by the engine, from the point of view of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// Advantage in hundredths of a pawn.
+    Centipawns(i32),
+    /// Mate in this many moves, negative when the side to move gets mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// Returns the same score from the other side's point of view.
+    pub fn flipped(self) -> Score {
+        match self {
+            Score::Centipawns(centipawns) => Score::Centipawns(-centipawns),
+            Score::Mate(moves) => Score::Mate(-moves),
+        }
+    }
+
+    /// Returns how much of the evaluation bar is White's, from 0 to 1.
+    pub fn white_share(self) -> f64 {
+        match self {
+            // Roughly White's winning chances, a pawn up is about 64%
+            Score::Centipawns(centipawns) => 1.0 / (1.0 + 10f64.powf(-centipawns as f64 / 400.0)),
+            Score::Mate(moves) if moves > 0 => 1.0,
+            Score::Mate(_) => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Centipawns(centipawns) => write!(f, "{:+.2}", *centipawns as f64 / 100.0),
+            Score::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// One principal variation from an `info` line of a running search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchInfo {
+    /// Rank of the variation, 1 for the best one.
+    pub multipv: usize,
+    pub depth: u32,
+    pub score: Score,
+    /// Moves the engine expects, starting with the one it would play.
+    pub pv: Vec<ChessMove>,
+}
+
+impl SearchInfo {
+    /// Parses an `info` line, returns `None` for lines without a score and a variation.
+    fn parse(line: &str) -> Option<SearchInfo> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("info") {
+            return None;
+        }
+
+        let mut multipv = 1;
+        let mut depth = 0;
+        let mut score = None;
+        while let Some(word) = words.next() {
+            match word {
+                "multipv" => multipv = words.next()?.parse().ok()?,
+                "depth" => depth = words.next()?.parse().ok()?,
+                "score" => {
+                    score = match (words.next()?, words.next()?.parse().ok()?) {
+                        ("cp", centipawns) => Some(Score::Centipawns(centipawns)),
+                        ("mate", moves) => Some(Score::Mate(moves)),
+                        _ => None,
+                    }
+                }
+                // The variation is always last on the line
+                "pv" => {
+                    let pv = words.map_while(ChessMove::from_uci).collect();
+                    return Some(SearchInfo { multipv, depth, score: score?, pv });
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Talks to a UCI engine without blocking the event loop.
+///
+/// The engine's output is read on a background thread and handed over line by line.
+pub struct UciEngine {
+    /// Name of the engine's program, e.g. `stockfish`.
+    pub name: String,
+    process: Child,
+    input: ChildStdin,
+    output: Receiver<String>,
+    /// Whether the engine is searching and hasn't answered with a move yet.
+    searching: bool,
+    /// Searches that were stopped and haven't answered yet, their output is skipped.
+    stopped_searches: usize,
+    /// Whether a command could not be written or the output ended, e.g. because the engine exited.
+    failed: bool,
+}
+
+impl UciEngine {
+    /// Starts an engine, `command` is the program followed by its arguments.
+    pub fn start(command: &str) -> io::Result<UciEngine> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "No engine command given")
+        })?;
+
+        let mut process = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let input = process.stdin.take().expect("Engine stdin is piped");
+        let stdout = process.stdout.take().expect("Engine stdout is piped");
+
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            // Stops when the engine exits or the controller drops the engine
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let name = Path::new(program)
+            .file_stem()
+            .map_or_else(|| program.to_string(), |stem| stem.to_string_lossy().into_owned());
+        let mut engine = UciEngine {
+            name,
+            process,
+            input,
+            output,
+            searching: false,
+            stopped_searches: 0,
+            failed: false,
+        };
+        engine.send("uci")?;
+        engine.send("ucinewgame")?;
+        engine.send("isready")?;
+        Ok(engine)
+    }
+
+    /// Sends one command to the engine.
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        let sent = writeln!(self.input, "{}", command).and_then(|()| self.input.flush());
+        if sent.is_err() {
+            self.failed = true;
+        }
+        sent
+    }
+
+    /// Returns whether a command could not be sent or the engine stopped writing, after which it
+    /// won't answer.
+    pub fn has_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Marks the engine failed, e.g. after it played an illegal move, so it isn't asked again.
+    pub fn fail(&mut self) {
+        self.failed = true;
+        self.searching = false;
+    }
+
+    /// Returns the lines the engine wrote since the last call, marking it failed once its output
+    /// has ended.
+    fn received_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            match self.output.try_recv() {
+                Ok(line) => lines.push(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.failed = true;
+                    self.searching = false;
+                    break;
+                }
+            }
+        }
+        lines
+    }
+
+    /// Returns whether the engine is searching for a move.
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
+    /// Sets an option the engine listed in its reply to `uci`, such as `MultiPV`.
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    /// Sends the position after `moves` have been played from `start`.
+    fn send_position(&mut self, start: &Position, moves: &[ChessMove]) -> io::Result<()> {
+        let mut position = if *start == Position::initial() {
+            String::from("position startpos")
+        } else {
+            format!("position fen {}", start.to_fen())
+        };
+        if !moves.is_empty() {
+            let moves: Vec<String> = moves.iter().map(|chess_move| chess_move.uci()).collect();
+            position.push_str(" moves ");
+            position.push_str(&moves.join(" "));
+        }
+        self.send(&position)
+    }
+
+    /// Starts searching for the best move after `moves` have been played from `start`.
+    pub fn go(&mut self, start: &Position, moves: &[ChessMove], move_time_ms: u64) -> io::Result<()> {
+        self.send_position(start, moves)?;
+        self.send(&format!("go movetime {}", move_time_ms))?;
+        self.searching = true;
+        Ok(())
+    }
+
+    /// Searches the position after `moves` have been played from `start` until stopped, stopping
+    /// the current search first.
+    ///
+    /// Progress is reported through `poll_info`.
+    pub fn go_infinite(&mut self, start: &Position, moves: &[ChessMove]) -> io::Result<()> {
+        self.stop()?;
+        self.send_position(start, moves)?;
+        self.send("go infinite")?;
+        self.searching = true;
+        Ok(())
+    }
+
+    /// Stops the current search, if any, and ignores what it still reports.
+    pub fn stop(&mut self) -> io::Result<()> {
+        if self.searching {
+            self.send("stop")?;
+            self.stopped_searches += 1;
+            self.searching = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the variations reported by the current search since the last call.
+    pub fn poll_info(&mut self) -> Vec<SearchInfo> {
+        let mut infos = Vec::new();
+        for line in self.received_lines() {
+            if line.starts_with("bestmove") {
+                if self.stopped_searches > 0 {
+                    self.stopped_searches -= 1;
+                } else {
+                    self.searching = false;
+                }
+            } else if self.stopped_searches == 0 {
+                infos.extend(SearchInfo::parse(&line));
+            }
+        }
+        infos
+    }
+
+    /// Returns the move the engine picked once it has finished searching.
+    pub fn poll_best_move(&mut self) -> Option<ChessMove> {
+        let mut best_move = None;
+        for line in self.received_lines() {
+            let mut words = line.split_whitespace();
+            if words.next() == Some("bestmove") {
+                self.searching = false;
+                best_move = words.next().and_then(ChessMove::from_uci);
+            }
+        }
+        best_move
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
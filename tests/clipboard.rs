@@ -0,0 +1,73 @@
+//! Copies and pastes positions as FEN with Ctrl+C and Ctrl+V, through a clipboard in memory.
+
+use chess_gui::{ChessController, ChessGraphicsSettings, ChessMove, Clipboard, MemoryClipboard, Opponent, Position};
+use piston::input::{Button, ButtonArgs, ButtonState, Event, Input, Key};
+
+const FEN: &str = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+
+/// Returns a local game with `text` on its clipboard.
+fn controller(text: &str) -> ChessController {
+    let mut controller = ChessController::new(Opponent::Local);
+    controller.clipboard = Box::new(MemoryClipboard { text: text.to_string() });
+    controller
+}
+
+fn key(settings: &ChessGraphicsSettings, controller: &mut ChessController, key: Key, state: ButtonState) {
+    let input = Input::Button(ButtonArgs { state, button: Button::Keyboard(key), scancode: None });
+    controller.event(settings, &Event::from(input));
+}
+
+/// Presses `shortcut` while holding Ctrl.
+fn ctrl(settings: &ChessGraphicsSettings, controller: &mut ChessController, shortcut: Key) {
+    key(settings, controller, Key::LCtrl, ButtonState::Press);
+    key(settings, controller, shortcut, ButtonState::Press);
+    key(settings, controller, shortcut, ButtonState::Release);
+    key(settings, controller, Key::LCtrl, ButtonState::Release);
+}
+
+#[test]
+fn ctrl_c_copies_the_position() {
+    let settings = ChessGraphicsSettings::new();
+    let mut controller = controller("");
+    assert!(controller.game.play(ChessMove::from_uci("e2e4").unwrap()));
+
+    ctrl(&settings, &mut controller, Key::C);
+    assert_eq!(controller.clipboard.get_text().unwrap(), controller.game.position.to_fen());
+    assert!(controller.game.notice.as_deref().is_some_and(|notice| notice.starts_with("Copied")));
+}
+
+#[test]
+fn ctrl_v_loads_the_position() {
+    let settings = ChessGraphicsSettings::new();
+    let mut controller = controller(&format!("  {}\n", FEN));
+    assert!(controller.game.play(ChessMove::from_uci("e2e4").unwrap()));
+
+    ctrl(&settings, &mut controller, Key::V);
+    assert_eq!(controller.game.position.to_fen(), FEN);
+    assert_eq!(controller.game.start.to_fen(), FEN);
+    assert!(controller.game.history.is_empty());
+}
+
+#[test]
+fn pasting_text_that_isnt_fen_keeps_the_game() {
+    let settings = ChessGraphicsSettings::new();
+    let mut controller = controller("not a position");
+
+    ctrl(&settings, &mut controller, Key::V);
+    assert_eq!(controller.game.position, Position::initial());
+    assert!(controller.game.notice.as_deref().is_some_and(|notice| notice.starts_with("Could not load FEN")));
+}
+
+#[test]
+fn copied_position_pastes_into_another_game() {
+    let settings = ChessGraphicsSettings::new();
+    let mut from = controller("");
+    for uci in ["d2d4", "g8f6", "c2c4"] {
+        assert!(from.game.play(ChessMove::from_uci(uci).unwrap()));
+    }
+    ctrl(&settings, &mut from, Key::C);
+
+    let mut to = controller(&from.clipboard.get_text().unwrap());
+    ctrl(&settings, &mut to, Key::V);
+    assert_eq!(to.game.position, from.game.position);
+}
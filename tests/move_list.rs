@@ -0,0 +1,58 @@
+//! Numbers and clicks the move list of games that start from a set-up position.
+
+use chess_gui::{ChessController, ChessGraphicsSettings, ChessMove, Opponent, Position};
+use piston::input::{Button, ButtonArgs, ButtonState, Event, Input, Loop, Motion, MouseButton, UpdateArgs};
+
+/// Black to move on move 12.
+const FEN: &str = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12";
+
+/// Returns a local game from `FEN` with `moves` played.
+fn controller(settings: &ChessGraphicsSettings, moves: &[&str]) -> ChessController {
+    let mut controller = ChessController::from_position(Opponent::Local, Position::from_fen(FEN).unwrap());
+    for uci in moves {
+        assert!(controller.game.play(ChessMove::from_uci(uci).unwrap()));
+    }
+    // The moves are recorded in the game tree on the next event
+    controller.event(settings, &Event::Loop(Loop::Update(UpdateArgs { dt: 1.0 / 60.0 })));
+    controller
+}
+
+/// Clicks the center of a move list cell.
+fn click_cell(settings: &ChessGraphicsSettings, controller: &mut ChessController, row: usize, column: usize) {
+    let [x, y, width, height] = settings.move_list_cell_rect(row, column);
+    let moved = Input::Move(Motion::MouseCursor([x + width / 2.0, y + height / 2.0]));
+    controller.event(settings, &Event::from(moved));
+    for state in [ButtonState::Press, ButtonState::Release] {
+        let input = Input::Button(ButtonArgs { state, button: Button::Mouse(MouseButton::Left), scancode: None });
+        controller.event(settings, &Event::from(input));
+    }
+}
+
+#[test]
+fn move_numbers_count_from_the_start_position() {
+    let settings = ChessGraphicsSettings::new();
+    let controller = controller(&settings, &["e8d8", "e1d1", "d8c8"]);
+
+    assert_eq!(controller.game.first_move_offset(), 1);
+    assert_eq!(controller.game.move_number(0), 12);
+    assert_eq!(controller.game.move_number(1), 13);
+    assert_eq!(controller.game.move_number(2), 13);
+    assert_eq!(controller.move_list_row_count(), 2);
+}
+
+#[test]
+fn clicking_a_move_shows_the_position_after_it() {
+    let settings = ChessGraphicsSettings::new();
+    let mut controller = controller(&settings, &["e8d8", "e1d1", "d8c8"]);
+
+    // Black's first move is in the second column of the first row
+    click_cell(&settings, &mut controller, 0, 1);
+    assert_eq!(controller.game.history.len(), 1);
+
+    click_cell(&settings, &mut controller, 1, 0);
+    assert_eq!(controller.game.history.len(), 2);
+
+    // White's cell of the first row has no move
+    click_cell(&settings, &mut controller, 0, 0);
+    assert_eq!(controller.game.history.len(), 2);
+}
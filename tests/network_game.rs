@@ -0,0 +1,478 @@
+//! Plays network games between a host and a client `ChessController` connected over an in-process
+//! transport, driving them with synthetic Piston input events.
+
+use std::net::{TcpListener, TcpStream};
+
+use chess_gui::chess_rules::{ChessMove, GameResult, PieceKind, Side};
+use chess_gui::networking::{State, MAX_CHAT_LENGTH, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use chess_gui::networking_protobuf::{
+    c2s_message, s2c_message, BoardState, C2sConnectRequest, C2sMessage, Move, S2cConnectAck, S2cMessage,
+};
+use chess_gui::{ChannelTransport, ChessController, ChessGraphicsSettings, Networking, Opponent, Position, Transport};
+use dynchess_lib::ChessPiece;
+use piston::input::{Button, ButtonArgs, ButtonState, Event, Input, Key, Loop, Motion, MouseButton, UpdateArgs};
+use prost::Message;
+
+/// Frames run for messages to get across, more than any exchange takes.
+const FRAMES: usize = 10;
+
+/// Returns a controller playing over `networking` from `start`, without saving the game.
+fn controller(networking: Networking, start: Position) -> ChessController {
+    let mut controller = ChessController::from_position(Opponent::Network(networking), start);
+    controller.game.autosave = None;
+    controller
+}
+
+/// Returns a host and a client connected to each other, before the handshake.
+fn connected_pair() -> (ChessController, ChessController) {
+    let (host_end, client_end) = ChannelTransport::pair();
+    (
+        controller(Networking::host(Box::new(host_end), None), Position::initial()),
+        controller(Networking::join(Box::new(client_end)), Position::initial()),
+    )
+}
+
+/// Returns a host and a client that have finished the handshake.
+fn playing_pair(settings: &ChessGraphicsSettings) -> (ChessController, ChessController) {
+    let (mut host, mut client) = connected_pair();
+    run(settings, &mut host, &mut client);
+    (host, client)
+}
+
+fn networking(controller: &ChessController) -> &Networking {
+    match &controller.game.opponent {
+        Opponent::Network(networking) => networking,
+        _ => panic!("Not a network game"),
+    }
+}
+
+/// Returns the event of a frame without input, where the game reads the network.
+fn frame() -> Event {
+    Event::Loop(Loop::Update(UpdateArgs { dt: 1.0 / 60.0 }))
+}
+
+/// Runs both sides for a few frames, so everything sent has arrived.
+fn run(settings: &ChessGraphicsSettings, host: &mut ChessController, client: &mut ChessController) {
+    for _ in 0..FRAMES {
+        host.event(settings, &frame());
+        client.event(settings, &frame());
+    }
+}
+
+fn press(settings: &ChessGraphicsSettings, controller: &mut ChessController, button: Button) {
+    for state in [ButtonState::Press, ButtonState::Release] {
+        let input = Input::Button(ButtonArgs { state, button, scancode: None });
+        controller.event(settings, &Event::from(input));
+    }
+}
+
+fn type_text(settings: &ChessGraphicsSettings, controller: &mut ChessController, text: &str) {
+    controller.event(settings, &Event::from(Input::Text(text.to_string())));
+}
+
+/// Clicks the center of a board index.
+fn click(settings: &ChessGraphicsSettings, controller: &mut ChessController, square: u8) {
+    let moved = Input::Move(Motion::MouseCursor(settings.square_center(square)));
+    controller.event(settings, &Event::from(moved));
+    press(settings, controller, Button::Mouse(MouseButton::Left));
+}
+
+/// Plays a move by clicking its squares, given as board indices.
+fn click_move(settings: &ChessGraphicsSettings, controller: &mut ChessController, from: u8, to: u8) {
+    click(settings, controller, from);
+    click(settings, controller, to);
+}
+
+#[test]
+fn handshake_connects_both_sides() {
+    let settings = ChessGraphicsSettings::new();
+    let (host, client) = playing_pair(&settings);
+
+    assert!(networking(&host).is_connected());
+    assert!(networking(&client).is_connected());
+    assert!(networking(&host).rejection.is_none());
+    assert!(networking(&client).supports("chat"));
+    // The host plays White and moves first
+    assert_eq!(networking(&host).state, State::Playing);
+    assert_eq!(networking(&client).state, State::WaitingForOpponent);
+}
+
+#[test]
+fn host_cant_move_before_the_client_has_joined() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = connected_pair();
+
+    // e2e4, neither played nor queued as a premove
+    click_move(&settings, &mut host, 12, 28);
+    assert!(host.game.history.is_empty());
+    assert!(host.game.premoves.is_empty());
+
+    run(&settings, &mut host, &mut client);
+    click_move(&settings, &mut host, 12, 28);
+    run(&settings, &mut host, &mut client);
+    assert!(client.game.position.board[28] == ChessPiece::WPawn);
+}
+
+#[test]
+fn moves_are_exchanged_and_turns_alternate() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+
+    // e2e4
+    click_move(&settings, &mut host, 12, 28);
+    assert_eq!(networking(&host).state, State::WaitingForOpponent);
+    run(&settings, &mut host, &mut client);
+    assert!(client.game.position.board[28] == ChessPiece::WPawn);
+    assert_eq!(networking(&client).state, State::Playing);
+
+    // The host can't move again during the client's turn, the click queues a premove instead
+    click_move(&settings, &mut host, 11, 27);
+    assert_eq!(host.game.history.len(), 1);
+
+    // e7e5
+    click_move(&settings, &mut client, 52, 36);
+    run(&settings, &mut host, &mut client);
+    assert!(host.game.position.board[36] == ChessPiece::BPawn);
+    // The queued d2d4 is played as soon as the client has moved
+    run(&settings, &mut host, &mut client);
+    assert!(client.game.position.board[27] == ChessPiece::WPawn);
+    assert_eq!(host.game.position.to_fen(), client.game.position.to_fen());
+}
+
+#[test]
+fn fools_mate_ends_the_game_on_both_boards() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+
+    for (side, from, to) in [(Side::White, 13, 21), (Side::Black, 52, 36), (Side::White, 14, 30), (Side::Black, 59, 31)] {
+        match side {
+            Side::White => click_move(&settings, &mut host, from, to),
+            Side::Black => click_move(&settings, &mut client, from, to),
+        }
+        run(&settings, &mut host, &mut client);
+    }
+
+    let mate = Some(GameResult::Checkmate { winner: Side::Black });
+    assert_eq!(host.game.position.result(), mate);
+    assert_eq!(client.game.position.result(), mate);
+    assert_eq!(host.game.history.len(), 4);
+    assert_eq!(client.game.history.len(), 4);
+}
+
+#[test]
+fn accepted_takeback_takes_back_on_both_boards() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+    click_move(&settings, &mut host, 12, 28);
+    run(&settings, &mut host, &mut client);
+
+    host.game.takeback();
+    run(&settings, &mut host, &mut client);
+    assert!(client.game.takeback_prompt().is_some());
+
+    press(&settings, &mut client, Button::Keyboard(Key::Y));
+    run(&settings, &mut host, &mut client);
+    assert!(host.game.history.is_empty());
+    assert!(client.game.history.is_empty());
+    assert_eq!(networking(&host).state, State::Playing);
+    assert_eq!(networking(&client).state, State::WaitingForOpponent);
+}
+
+#[test]
+fn takeback_answered_after_the_opponent_left_keeps_the_moves() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+    click_move(&settings, &mut host, 12, 28);
+    run(&settings, &mut host, &mut client);
+    host.game.takeback();
+    run(&settings, &mut host, &mut client);
+    drop(host);
+
+    press(&settings, &mut client, Button::Keyboard(Key::Y));
+    assert_eq!(client.game.history.len(), 1);
+    assert!(client.game.notice.as_deref().is_some_and(|notice| notice.contains("Could not answer the takeback")));
+}
+
+#[test]
+fn takeback_after_the_opponent_left_shows_a_notice() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+    click_move(&settings, &mut host, 12, 28);
+    run(&settings, &mut host, &mut client);
+    drop(client);
+
+    host.game.takeback();
+    assert!(host.game.notice.as_deref().is_some_and(|notice| notice.contains("Could not ask for a takeback")));
+    assert_eq!(host.game.history.len(), 1);
+}
+
+#[test]
+fn chat_messages_reach_the_opponent() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+
+    press(&settings, &mut client, Button::Keyboard(Key::Tab));
+    type_text(&settings, &mut client, "good luck");
+    press(&settings, &mut client, Button::Keyboard(Key::Return));
+    run(&settings, &mut host, &mut client);
+
+    let texts: Vec<&str> = host.game.chat.iter().map(|line| line.text.as_str()).collect();
+    assert_eq!(texts, ["good luck"]);
+    // Typing went to the chat, not the move input
+    assert!(client.move_input.is_empty());
+    assert!(client.chat_input.is_none());
+}
+
+#[test]
+fn long_chat_messages_are_cut_off() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host, mut client) = playing_pair(&settings);
+
+    press(&settings, &mut client, Button::Keyboard(Key::Tab));
+    type_text(&settings, &mut client, &"a".repeat(MAX_CHAT_LENGTH + 100));
+    press(&settings, &mut client, Button::Keyboard(Key::Return));
+    run(&settings, &mut host, &mut client);
+
+    assert_eq!(host.game.chat.len(), 1);
+    assert_eq!(host.game.chat[0].text.len(), MAX_CHAT_LENGTH);
+}
+
+#[test]
+fn spectator_leaving_doesnt_end_the_game() {
+    let settings = ChessGraphicsSettings::new();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let address = listener.local_addr().unwrap();
+    let (host_end, client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), Some(listener)), Position::initial());
+    let mut client = controller(Networking::join(Box::new(client_end)), Position::initial());
+    run(&settings, &mut host, &mut client);
+
+    let spectator = TcpStream::connect(address).unwrap();
+    run(&settings, &mut host, &mut client);
+    assert_eq!(networking(&host).spectator_count(), 1);
+
+    drop(spectator);
+    host.game.send_chat("still here");
+    run(&settings, &mut host, &mut client);
+    assert_eq!(networking(&host).spectator_count(), 0);
+
+    click_move(&settings, &mut host, 12, 28);
+    run(&settings, &mut host, &mut client);
+    assert!(client.game.position.board[28] == ChessPiece::WPawn);
+    assert_eq!(client.game.chat.len(), 1);
+}
+
+#[test]
+fn refused_connection_is_shown_to_the_client() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host_end, client_end) = ChannelTransport::pair();
+    let mut client = controller(Networking::join(Box::new(client_end)), Position::initial());
+
+    let ack = S2cMessage {
+        msg: Some(s2c_message::Msg::ConnectAck(S2cConnectAck {
+            success: false,
+            error: Some(String::from("the game is full")),
+            ..Default::default()
+        })),
+    };
+    host_end.send(&ack.encode_to_vec()).unwrap();
+    for _ in 0..FRAMES {
+        client.event(&settings, &frame());
+    }
+
+    assert!(!networking(&client).is_connected());
+    assert!(client.game.status_text().contains("the game is full"));
+}
+
+/// Sends the connect request of an older build to `host` and returns the host's acknowledgement.
+fn connect_as(
+    settings: &ChessGraphicsSettings,
+    host: &mut ChessController,
+    client_end: &mut ChannelTransport,
+    request: C2sConnectRequest,
+) -> S2cConnectAck {
+    let request = C2sMessage { msg: Some(c2s_message::Msg::ConnectRequest(request)) };
+    client_end.send(&request.encode_to_vec()).unwrap();
+    host.event(settings, &frame());
+
+    match S2cMessage::decode(&client_end.poll().unwrap().unwrap()[..]).unwrap().msg {
+        Some(s2c_message::Msg::ConnectAck(ack)) => ack,
+        msg => panic!("Expected a connect acknowledgement, got {:?}", msg),
+    }
+}
+
+#[test]
+fn client_without_versions_is_refused() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), None), Position::initial());
+
+    // A build from before versions and capabilities were sent
+    let ack = connect_as(&settings, &mut host, &mut client_end, C2sConnectRequest { game_id: 1, ..Default::default() });
+    assert!(!ack.success);
+    assert!(ack.error.is_some_and(|error| error.contains("protocol version 0")));
+    assert!(networking(&host).rejection.is_some());
+}
+
+#[test]
+fn client_without_tcp_framing_is_refused() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), None), Position::initial());
+
+    let request = C2sConnectRequest { game_id: 1, protocol_version: 1, ..Default::default() };
+    let ack = connect_as(&settings, &mut host, &mut client_end, request);
+    assert!(!ack.success);
+    assert!(ack.error.is_some_and(|error| error.contains("protocol version 1")));
+}
+
+#[test]
+fn moves_before_the_connect_request_are_left_out() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), None), Position::initial());
+
+    let e7e5 = Move { from_square: 52, to_square: 36, promotion: None };
+    client_end.send(&C2sMessage { msg: Some(c2s_message::Msg::Move(e7e5)) }.encode_to_vec()).unwrap();
+    host.event(&settings, &frame());
+
+    assert!(!networking(&host).is_connected());
+    assert!(host.game.history.is_empty());
+    assert!(!host.game.is_players_turn());
+}
+
+#[test]
+fn client_without_capabilities_plays_without_optional_features() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), None), Position::initial());
+
+    let request = C2sConnectRequest { game_id: 1, protocol_version: MIN_PROTOCOL_VERSION, ..Default::default() };
+    let ack = connect_as(&settings, &mut host, &mut client_end, request);
+    assert!(ack.success);
+    assert!(networking(&host).is_connected());
+    assert!(!host.game.has_chat());
+
+    click_move(&settings, &mut host, 12, 28);
+    host.game.takeback();
+    assert_eq!(host.game.history.len(), 1);
+    assert!(host.game.notice.as_deref().is_some_and(|notice| notice.contains("doesn't support takebacks")));
+}
+
+#[test]
+fn set_up_position_is_refused_for_clients_that_cant_start_from_it() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let start = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    let mut host = controller(Networking::host(Box::new(host_end), None), start);
+
+    let request = C2sConnectRequest { game_id: 1, protocol_version: MIN_PROTOCOL_VERSION, ..Default::default() };
+    let ack = connect_as(&settings, &mut host, &mut client_end, request);
+    assert!(!ack.success);
+    assert!(ack.error.is_some());
+    assert!(networking(&host).rejection.is_some());
+}
+
+#[test]
+fn set_up_position_is_sent_to_the_client() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, client_end) = ChannelTransport::pair();
+    let start = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+    let mut host = controller(Networking::host(Box::new(host_end), None), start.clone());
+    let mut client = controller(Networking::join(Box::new(client_end)), Position::initial());
+    run(&settings, &mut host, &mut client);
+
+    assert_eq!(client.game.start.to_fen(), start.to_fen());
+    // Black is to move, so the client moves first
+    assert_eq!(networking(&client).state, State::Playing);
+    assert_eq!(networking(&host).state, State::WaitingForOpponent);
+
+    // e8d8
+    click_move(&settings, &mut client, 60, 59);
+    run(&settings, &mut host, &mut client);
+    assert!(host.game.position.board[59] == ChessPiece::BKing);
+}
+
+#[test]
+fn promotions_reach_the_opponent() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, client_end) = ChannelTransport::pair();
+    let start = Position::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let mut host = controller(Networking::host(Box::new(host_end), None), start);
+    let mut client = controller(Networking::join(Box::new(client_end)), Position::initial());
+    run(&settings, &mut host, &mut client);
+
+    // a7a8n
+    assert!(host.game.play(ChessMove { from: 48, to: 56, promotion: Some(PieceKind::Knight) }));
+    run(&settings, &mut host, &mut client);
+    assert!(client.game.position.board[56] == ChessPiece::WKnight);
+    assert_eq!(host.game.position.to_fen(), client.game.position.to_fen());
+}
+
+#[test]
+fn moves_off_the_board_are_left_out() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), None), Position::initial());
+    let request = C2sConnectRequest { game_id: 1, protocol_version: PROTOCOL_VERSION, ..Default::default() };
+    assert!(connect_as(&settings, &mut host, &mut client_end, request).success);
+
+    // e2e4, then a reply from square 52 to square 200
+    click_move(&settings, &mut host, 12, 28);
+    let reply = Move { from_square: 52, to_square: 200, promotion: None };
+    client_end.send(&C2sMessage { msg: Some(c2s_message::Msg::Move(reply)) }.encode_to_vec()).unwrap();
+    host.event(&settings, &frame());
+
+    assert_eq!(host.game.history.len(), 1);
+    assert_eq!(networking(&host).state, State::WaitingForOpponent);
+}
+
+#[test]
+fn illegal_moves_from_the_opponent_show_a_notice() {
+    let settings = ChessGraphicsSettings::new();
+    let (host_end, mut client_end) = ChannelTransport::pair();
+    let mut host = controller(Networking::host(Box::new(host_end), None), Position::initial());
+    let request = C2sConnectRequest { game_id: 1, protocol_version: PROTOCOL_VERSION, ..Default::default() };
+    assert!(connect_as(&settings, &mut host, &mut client_end, request).success);
+
+    // e2e4, then e7e2
+    click_move(&settings, &mut host, 12, 28);
+    let reply = Move { from_square: 52, to_square: 12, promotion: None };
+    client_end.send(&C2sMessage { msg: Some(c2s_message::Msg::Move(reply)) }.encode_to_vec()).unwrap();
+    host.event(&settings, &frame());
+
+    assert_eq!(host.game.history.len(), 1);
+    assert!(host.game.notice.as_deref().is_some_and(|notice| notice.contains("illegal move: e7e2")));
+}
+
+#[test]
+fn repeated_connect_ack_doesnt_restart_the_game() {
+    let settings = ChessGraphicsSettings::new();
+    let (mut host_end, client_end) = ChannelTransport::pair();
+    let mut client = controller(Networking::join(Box::new(client_end)), Position::initial());
+    assert!(host_end.poll().unwrap().is_some());
+
+    let ack = S2cConnectAck {
+        success: true,
+        game_id: Some(1),
+        starting_position: Some(BoardState { fen_string: String::from("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1") }),
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: vec![String::from("start-position")],
+        ..Default::default()
+    };
+    let ack = S2cMessage { msg: Some(s2c_message::Msg::ConnectAck(ack)) }.encode_to_vec();
+    host_end.send(&ack).unwrap();
+    for _ in 0..FRAMES {
+        client.event(&settings, &frame());
+    }
+
+    // e8d8, then the acknowledgement arrives again
+    click_move(&settings, &mut client, 60, 59);
+    host_end.send(&ack).unwrap();
+    for _ in 0..FRAMES {
+        client.event(&settings, &frame());
+    }
+    assert_eq!(client.game.history.len(), 1);
+    assert!(client.game.position.board[59] == ChessPiece::BKing);
+}
@@ -0,0 +1,95 @@
+//! Sends messages between two `TcpTransport`s connected over loopback.
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use chess_gui::networking_protobuf::{s2c_message, ChatMessage, S2cMessage};
+use chess_gui::{TcpTransport, Transport};
+use prost::Message;
+
+/// Polls made for a message to arrive, a millisecond apart.
+const POLLS: usize = 1000;
+
+/// Returns the host and client ends of a loopback connection.
+fn connected_pair() -> (TcpTransport, TcpTransport) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (host, _) = listener.accept().unwrap();
+    (TcpTransport::new(host).unwrap(), TcpTransport::new(client).unwrap())
+}
+
+/// Polls until a message arrives.
+fn receive(transport: &mut TcpTransport) -> Vec<u8> {
+    for _ in 0..POLLS {
+        if let Some(message) = transport.poll().unwrap() {
+            return message;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("No message arrived");
+}
+
+fn chat(text: &str) -> Vec<u8> {
+    let chat = ChatMessage { sender: String::from("White"), text: text.to_string() };
+    S2cMessage { msg: Some(s2c_message::Msg::Chat(chat)) }.encode_to_vec()
+}
+
+#[test]
+fn back_to_back_messages_arrive_one_at_a_time() {
+    let (mut host, mut client) = connected_pair();
+    host.send(&chat("good luck")).unwrap();
+    host.send(&chat("have fun")).unwrap();
+    // Both messages are in the socket before the first poll
+    thread::sleep(Duration::from_millis(50));
+
+    assert_eq!(receive(&mut client), chat("good luck"));
+    assert_eq!(receive(&mut client), chat("have fun"));
+    assert_eq!(client.poll().unwrap(), None);
+}
+
+#[test]
+fn long_messages_arrive_whole() {
+    let (mut host, mut client) = connected_pair();
+    let message = chat(&"a".repeat(5000));
+    host.send(&message).unwrap();
+
+    let received = receive(&mut client);
+    assert_eq!(received, message);
+    assert!(S2cMessage::decode(&received[..]).is_ok());
+}
+
+#[test]
+fn closed_connection_fails_to_poll() {
+    let (mut host, mut client) = connected_pair();
+    host.close();
+
+    let closed = (0..POLLS).any(|_| {
+        thread::sleep(Duration::from_millis(1));
+        client.poll().is_err()
+    });
+    assert!(closed);
+}
+
+#[test]
+fn messages_the_socket_cant_take_at_once_arrive_whole() {
+    let (mut host, mut client) = connected_pair();
+    // More than the socket takes at once, so the rest goes out as the host polls
+    let messages: Vec<Vec<u8>> = (0..10).map(|i| chat(&i.to_string().repeat(900_000))).collect();
+    for message in &messages {
+        host.send(message).unwrap();
+    }
+
+    let mut received = Vec::new();
+    for _ in 0..POLLS {
+        host.poll().unwrap();
+        while let Some(message) = client.poll().unwrap() {
+            received.push(message);
+        }
+        if received.len() == messages.len() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(received, messages);
+}